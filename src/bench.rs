@@ -0,0 +1,207 @@
+use crate::{
+    interrupt::{Interrupt, Interrupted},
+    keys::{Action, Key, NUDGE},
+    packet_log::PacketLog,
+    rollup::{Bucket, Rollups},
+    run::Run,
+    search::{Knee, MaxSize},
+    stat::Stat,
+    ui::{RunComponent, Ui},
+};
+use anyhow::Error;
+use futures_util::pin_mut;
+use std::{collections::HashMap, net::SocketAddr};
+use superconsole::{state, State};
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+
+/// Everything a bench has run.
+#[derive(Default)]
+pub struct Results {
+    pub runs: Vec<Run<SocketAddr>>,
+    pub stats: HashMap<usize, Stat>,
+    pub knees: Vec<Knee>,
+    pub max_sizes: Vec<MaxSize>,
+    pub buckets: Vec<Bucket>,
+    /// commands typed at the console
+    pub actions: Vec<Action>,
+}
+
+/// Executes runs one after the other, as they are created, and keeps their
+/// results.
+pub struct Bench {
+    results: Results,
+    components: Vec<RunComponent>,
+    ui: Ui,
+    packet_log: Option<PacketLog>,
+    interrupt: Interrupt,
+}
+
+impl Bench {
+    pub fn new(ui: Ui, packet_log: Option<PacketLog>, interrupt: Interrupt) -> Bench {
+        Bench {
+            results: Results::default(),
+            components: vec![],
+            ui,
+            packet_log,
+            interrupt,
+        }
+    }
+
+    /// Id the next run will get.
+    pub fn next_id(&self) -> usize {
+        self.results.runs.len()
+    }
+
+    /// Execute `run` to completion, assigning it the next id.
+    ///
+    /// Fails with `Interrupted` once the bench is interrupted, after keeping
+    /// what the run in progress has counted so far.
+    pub async fn execute(&mut self, mut run: Run<SocketAddr>) -> Result<&Stat, Error> {
+        if self.interrupt.is_set() {
+            return Err(Interrupted.into());
+        }
+        // keys typed between runs, while nothing could act on them
+        self.ui.clear_keys();
+        let idx = self.results.runs.len();
+        run.id = idx as u32;
+        self.components.push(RunComponent {
+            id: idx,
+            hertz: run.hertz,
+            byte_size: run.byte_size,
+            stage: run.stage.clone(),
+            target: run.addr,
+            max_loss: run.max_loss,
+            ramp: run.ramp,
+            traffic: run.traffic,
+            trace: run.trace.clone(),
+            payload: run.payload.clone(),
+            legacy: run.session.legacy,
+            scale: 1.0,
+            paused: false,
+        });
+        self.results.runs.push(run);
+
+        let run = &self.results.runs[idx];
+        let mut rollups = run.bucket.map(|len| Rollups::new(idx, Instant::now(), len));
+        let mut interrupt = self.interrupt.clone();
+        let (mut interrupted, mut skipped) = (false, false);
+        let started = Instant::now();
+        let stream = run.start();
+        pin_mut!(stream);
+        loop {
+            let event = tokio::select! {
+                biased;
+                () = interrupt.wait() => {
+                    interrupted = true;
+                    break;
+                }
+                key = self.ui.next_key() => {
+                    let component = &mut self.components[idx];
+                    let key = match key {
+                        Key::Pause | Key::Resume => {
+                            component.paused = !component.paused;
+                            run.control.set_paused(component.paused);
+                            if component.paused { Key::Pause } else { Key::Resume }
+                        }
+                        Key::Faster | Key::Slower => {
+                            component.scale *= if key == Key::Faster { NUDGE } else { 1.0 / NUDGE };
+                            run.control.set_scale(component.scale);
+                            key
+                        }
+                        Key::Skip => {
+                            skipped = true;
+                            key
+                        }
+                        Key::Quit => {
+                            interrupted = true;
+                            key
+                        }
+                    };
+                    self.results.actions.push(Action {
+                        run: idx,
+                        at_secs: started.elapsed().as_secs_f64(),
+                        key,
+                        hertz: matches!(key, Key::Faster | Key::Slower)
+                            .then(|| run.hertz * component.scale),
+                    });
+                    if skipped || interrupted {
+                        break;
+                    }
+                    self.ui.render(&view(&self.results, &self.components))?;
+                    continue;
+                }
+                event = stream.next() => match event {
+                    Some(event) => event?,
+                    None => break,
+                },
+            };
+            self.results.stats.entry(idx).or_default().record(&event);
+            if let Some(log) = &mut self.packet_log {
+                log.write(idx, &event)?;
+            }
+            if let Some(rollups) = &mut rollups {
+                for bucket in rollups.record(&event, Instant::now()) {
+                    self.ui.finish_bucket(&bucket)?;
+                    self.results.buckets.push(bucket);
+                }
+            }
+            self.ui.render(&view(&self.results, &self.components))?;
+        }
+        if let Some(rollups) = rollups {
+            let bucket = rollups.finish(Instant::now());
+            self.ui.finish_bucket(&bucket)?;
+            self.results.buckets.push(bucket);
+        }
+
+        let stat = self.results.stats.entry(idx).or_default();
+        stat.done = true;
+        stat.interrupted = interrupted;
+        stat.skipped = skipped;
+        stat.decision = run.control.decision();
+        self.ui
+            .finish_run(idx, &view(&self.results, &self.components))?;
+        if interrupted {
+            return Err(Interrupted.into());
+        }
+        Ok(&self.results.stats[&idx])
+    }
+
+    /// Record the knee of a finished rate search, assigning it the next id.
+    pub fn add_knee(&mut self, mut knee: Knee) -> Result<(), Error> {
+        knee.id = self.results.knees.len();
+        self.results.knees.push(knee);
+        let knee = self.results.knees.last().unwrap();
+        self.ui
+            .finish_search(knee, &view(&self.results, &self.components))
+    }
+
+    /// Record the outcome of a finished size search, assigning it the next id.
+    pub fn add_max_size(&mut self, mut max_size: MaxSize) -> Result<(), Error> {
+        max_size.id = self.results.max_sizes.len();
+        self.results.max_sizes.push(max_size);
+        let max_size = self.results.max_sizes.last().unwrap();
+        self.ui
+            .finish_search(max_size, &view(&self.results, &self.components))
+    }
+
+    /// Restore the terminal and flush the packet log.
+    pub fn finish(self) -> Result<Results, Error> {
+        self.ui.finalize(&view(&self.results, &self.components))?;
+        if let Some(log) = self.packet_log {
+            log.finish()?;
+        }
+        Ok(self.results)
+    }
+}
+
+/// What the UI draws.
+fn view<'a>(results: &'a Results, components: &'a Vec<RunComponent>) -> State<'a> {
+    state!(
+        &results.stats,
+        components,
+        &results.knees,
+        &results.max_sizes,
+        &results.buckets
+    )
+}
@@ -0,0 +1,140 @@
+use crate::stat::{ms, Stat};
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// A run placed in a grid by its rate and size.
+pub struct Point<'a> {
+    pub run: usize,
+    pub stage: Option<&'a str>,
+    pub target: SocketAddr,
+    pub hertz: f32,
+    pub byte_size: usize,
+    pub max_loss: Option<f32>,
+    pub stat: &'a Stat,
+}
+
+/// The runs of one stage and target laid out by rate (rows) and packet size
+/// (columns).
+#[derive(Debug, Clone, Serialize)]
+pub struct Grid {
+    pub stage: Option<String>,
+    pub target: SocketAddr,
+    /// rates of the rows, ascending
+    pub rates_hz: Vec<f32>,
+    /// sizes of the columns, ascending
+    pub sizes: Vec<usize>,
+    /// `cells[row][column]`, `None` where no run had that rate and size
+    pub cells: Vec<Vec<Option<Cell>>>,
+}
+
+/// The outcome of the run at a rate and size, the last one if several were.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Cell {
+    pub run: usize,
+    pub loss: f32,
+    /// the ratio `max_loss` applies to, see `Stat::failure`
+    pub failure: f32,
+    pub rtt_p50_ms: Option<f64>,
+    pub passed: Option<bool>,
+}
+
+/// A cell at its rate and size, before the grid's rows and columns are known.
+type Placed = (f32, usize, Cell);
+
+/// One grid per stage and target, in the order they were first run. Grids of
+/// a single cell are left out.
+pub fn grids<'a>(points: impl IntoIterator<Item = Point<'a>>) -> Vec<Grid> {
+    let mut grids: Vec<(Grid, Vec<Placed>)> = vec![];
+    for point in points {
+        let cell = Cell {
+            run: point.run,
+            loss: point.stat.loss(),
+            failure: point.stat.failure(),
+            rtt_p50_ms: point.stat.latency().map(|l| ms(l.p50)),
+            passed: point
+                .max_loss
+                .filter(|_| !point.stat.is_partial())
+                .map(|max_loss| point.stat.passed(max_loss)),
+        };
+        let stage = point.stage.map(str::to_owned);
+        let idx = match grids
+            .iter()
+            .position(|(g, _)| g.stage == stage && g.target == point.target)
+        {
+            Some(idx) => idx,
+            None => {
+                let grid = Grid {
+                    stage,
+                    target: point.target,
+                    rates_hz: vec![],
+                    sizes: vec![],
+                    cells: vec![],
+                };
+                grids.push((grid, vec![]));
+                grids.len() - 1
+            }
+        };
+        grids[idx].1.push((point.hertz, point.byte_size, cell));
+    }
+
+    grids
+        .into_iter()
+        .filter_map(|(mut grid, cells)| {
+            grid.rates_hz = cells.iter().map(|c| c.0).collect();
+            grid.rates_hz.sort_by(f32::total_cmp);
+            grid.rates_hz.dedup();
+            grid.sizes = cells.iter().map(|c| c.1).collect();
+            grid.sizes.sort_unstable();
+            grid.sizes.dedup();
+            if grid.rates_hz.len() * grid.sizes.len() < 2 {
+                return None;
+            }
+            grid.cells = vec![vec![None; grid.sizes.len()]; grid.rates_hz.len()];
+            for (hertz, byte_size, cell) in cells {
+                let row = grid.rates_hz.iter().position(|&r| r == hertz)?;
+                let column = grid.sizes.iter().position(|&s| s == byte_size)?;
+                grid.cells[row][column] = Some(cell);
+            }
+            Some(grid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_rates_by_sizes() {
+        let target = "127.0.0.1:2001".parse().unwrap();
+        let mut stat = Stat::default();
+        stat.sent = 10;
+        stat.missed = 1;
+        let runs = [(16.0, 200), (4.0, 100), (4.0, 200), (16.0, 200)];
+        let points = runs
+            .iter()
+            .enumerate()
+            .map(|(run, &(hertz, byte_size))| Point {
+                run,
+                stage: None,
+                target,
+                hertz,
+                byte_size,
+                max_loss: Some(0.05),
+                stat: &stat,
+            });
+        let grids = grids(points);
+        assert_eq!(grids.len(), 1);
+        let grid = &grids[0];
+        assert_eq!(grid.rates_hz, vec![4.0, 16.0]);
+        assert_eq!(grid.sizes, vec![100, 200]);
+        let runs: Vec<Vec<_>> = grid
+            .cells
+            .iter()
+            .map(|row| row.iter().map(|c| c.map(|c| c.run)).collect())
+            .collect();
+        assert_eq!(runs, vec![vec![Some(1), Some(2)], vec![None, Some(3)]]);
+        assert_eq!(grid.cells[0][0].unwrap().failure, 0.1);
+        assert_eq!(grid.cells[0][0].unwrap().passed, Some(false));
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, io};
+use tokio::sync::{mpsc, watch};
+
+/// Exit status of a forced quit, as for a shell job killed by SIGINT.
+const FORCED_EXIT: i32 = 130;
+
+/// Whether the process has been asked to stop, by SIGINT or SIGTERM.
+///
+/// The first signal sets it, so the bench can wrap up and write its results.
+/// A second one exits immediately.
+#[derive(Clone)]
+pub struct Interrupt(watch::Receiver<bool>);
+
+impl Interrupt {
+    /// Start listening for signals.
+    pub fn listen() -> io::Result<Interrupt> {
+        let (signal_tx, mut signals) = mpsc::unbounded_channel();
+        {
+            let signal_tx = signal_tx.clone();
+            tokio::spawn(async move {
+                while tokio::signal::ctrl_c().await.is_ok() && signal_tx.send(()).is_ok() {}
+            });
+        }
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut term = signal(SignalKind::terminate())?;
+            tokio::spawn(async move {
+                while term.recv().await.is_some() && signal_tx.send(()).is_ok() {}
+            });
+        }
+        #[cfg(not(unix))]
+        drop(signal_tx);
+
+        let (set, interrupt) = watch::channel(false);
+        tokio::spawn(async move {
+            if signals.recv().await.is_none() {
+                return;
+            }
+            set.send_replace(true);
+            if signals.recv().await.is_some() {
+                crate::keys::restore();
+                eprintln!("interrupted again, quitting");
+                std::process::exit(FORCED_EXIT);
+            }
+        });
+        Ok(Interrupt(interrupt))
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolve once the interrupt is set.
+    pub async fn wait(&mut self) {
+        while !self.is_set() {
+            if self.0.changed().await.is_err() {
+                // no signal can arrive anymore
+                futures::future::pending().await
+            }
+        }
+    }
+}
+
+/// The error that stops a bench once it is interrupted.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
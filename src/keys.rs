@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::io::Read;
+use tokio::sync::mpsc;
+
+/// A command typed at the console while a run is in progress.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Key {
+    /// `p`, stops sending until it is typed again
+    Pause,
+    /// `p` while paused
+    Resume,
+    /// `s`, ends the run and moves on to the next
+    Skip,
+    /// `q`, stops the bench like an interrupt
+    Quit,
+    /// `+`, raises the rate by `NUDGE`
+    Faster,
+    /// `-`, lowers the rate by `NUDGE`
+    Slower,
+}
+
+/// A command applied to a run, as noted in the results.
+#[derive(Debug, Clone, Serialize)]
+pub struct Action {
+    pub run: usize,
+    /// seconds since the run started
+    pub at_secs: f64,
+    pub key: Key,
+    /// send rate after a nudge
+    pub hertz: Option<f32>,
+}
+
+/// Factor by which `+` and `-` change the rate.
+pub const NUDGE: f32 = 1.1;
+
+impl Key {
+    fn from_byte(byte: u8) -> Option<Key> {
+        match byte {
+            b'p' => Some(Key::Pause),
+            b's' => Some(Key::Skip),
+            b'q' => Some(Key::Quit),
+            b'+' | b'=' => Some(Key::Faster),
+            b'-' | b'_' => Some(Key::Slower),
+            _ => None,
+        }
+    }
+}
+
+/// Keys read one at a time from the terminal on stdin, which stops echoing
+/// them until this is dropped.
+pub struct Keys(mpsc::UnboundedReceiver<Key>);
+
+impl Keys {
+    /// `None` unless stdin is a terminal.
+    pub fn listen() -> Option<Keys> {
+        if !terminal::read_keys() {
+            return None;
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        // a plain thread, a blocking read would hold up the runtime's shutdown
+        std::thread::spawn(move || {
+            let mut byte = [0];
+            while let Ok(1) = std::io::stdin().read(&mut byte) {
+                if let Some(key) = Key::from_byte(byte[0]) {
+                    if tx.send(key).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Some(Keys(rx))
+    }
+
+    /// Drop the keys typed so far.
+    pub fn clear(&mut self) {
+        while self.0.try_recv().is_ok() {}
+    }
+
+    pub async fn next(&mut self) -> Key {
+        match self.0.recv().await {
+            Some(key) => key,
+            None => futures::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        terminal::restore();
+    }
+}
+
+pub use terminal::restore;
+
+#[cfg(unix)]
+mod terminal {
+    use std::sync::Mutex;
+
+    /// Terminal settings of stdin before keys were read from it.
+    static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    /// Deliver keys on stdin as they are typed, without echo. Signals such as
+    /// Ctrl-C are still raised.
+    pub fn read_keys() -> bool {
+        // SAFETY: `termios` is plain data filled in by `tcgetattr`, fd 0 is
+        // only inspected and configured
+        unsafe {
+            if libc::isatty(0) != 1 {
+                return false;
+            }
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(0, &mut termios) != 0 {
+                return false;
+            }
+            let saved = termios;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(0, libc::TCSANOW, &termios) != 0 {
+                return false;
+            }
+            *SAVED.lock().unwrap() = Some(saved);
+        }
+        true
+    }
+
+    /// Restore the terminal settings of stdin, if keys were read from it.
+    pub fn restore() {
+        if let Some(saved) = SAVED.lock().unwrap().take() {
+            // SAFETY: `saved` came from `tcgetattr` on the same fd
+            unsafe {
+                libc::tcsetattr(0, libc::TCSANOW, &saved);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub fn read_keys() -> bool {
+        false
+    }
+
+    pub fn restore() {}
+}
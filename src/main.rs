@@ -1,195 +1,510 @@
-use anyhow::Error;
+use anyhow::{Context, Error};
 use argh::FromArgs;
-use async_stream::stream;
-use futures_util::{pin_mut, FutureExt};
-use std::{collections::HashMap, convert::TryInto, sync::Arc, time::Duration};
-use superconsole::{
-    components::splitting::{Split, SplitKind},
-    state,
-    style::Stylize,
-    Component, Dimensions, Direction, DrawMode, Line, Span, State, SuperConsole,
-};
-use tokio::{
-    net::{ToSocketAddrs, UdpSocket},
-    time,
-};
-use tokio_stream::{Stream, StreamExt};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::net::UdpSocket;
+
+mod bench;
+mod grid;
+mod interrupt;
+mod keys;
+mod packet_log;
+mod payload;
+mod plan;
+mod profile;
+mod protocol;
+mod report;
+mod rollup;
+mod run;
+mod search;
+mod serve;
+mod stat;
+mod sweep;
+mod trace;
+mod track;
+mod ui;
+
+use bench::Bench;
+use grid::Point;
+use interrupt::{Interrupt, Interrupted};
+use packet_log::PacketLog;
+use payload::Payload;
+use plan::{Plan, Stage};
+use profile::{Model, Ramp, Shape};
+use report::{Format, Meta, Report, RunResult};
+use run::{Run, Session};
+use search::{Knee, MaxSize, Search, SizeSearch};
+use stat::Sprt;
+use sweep::{Order, Spec};
+use trace::Trace;
+use ui::Ui;
 
 #[derive(FromArgs)]
 /// Throughput tester
 struct Args {
     /// address to bind too
     #[argh(option)]
-    bind: std::net::SocketAddr,
+    bind: Option<SocketAddr>,
 
     /// target of loopback service
     #[argh(option)]
-    target: std::net::SocketAddr,
-}
+    target: Option<SocketAddr>,
 
-#[derive(Default)]
-struct Stat {
-    sent: usize,
-    missed: usize,
-}
+    /// test plan (TOML) describing the stages to run, instead of the rate and size options
+    #[argh(option)]
+    plan: Option<PathBuf>,
 
-#[derive(Debug)]
-struct RunComponent {
-    id: usize,
-    hertz: f32,
-    byte_size: usize,
-}
+    /// packet rates in hertz, as a list of values and ranges (e.g. `1,2,4..64*2`)
+    #[argh(option, default = "Spec(vec![4.0, 8.0, 16.0])")]
+    rates: Spec<f32>,
 
-impl Component for RunComponent {
-    fn draw_unchecked(
-        &self,
-        state: &State,
-        _dimensions: Dimensions,
-        _mode: DrawMode,
-    ) -> Result<Vec<Line>, Error> {
-        let stat = state.get::<HashMap<usize, Stat>>()?.get(&self.id);
-        let mut messages = vec![];
-        messages.push(vec![format!("{}. Rate: {}hz", self.id, self.hertz)].try_into()?);
-        messages.push(vec![format!("   Packet Size: {} bytes", self.byte_size)].try_into()?);
-        match stat {
-            Some(stat) => {
-                let sent = Span::new_styled(format!("   Sent: {} ", stat.sent).to_owned().blue())?;
-                let missed =
-                    Span::new_styled(format!("Missed: {} ", stat.missed).to_owned().yellow())?;
-                messages.push(superconsole::line!(sent, missed));
-            }
-            None => {
-                let not = Span::new_styled("   Not Started".to_owned().red().bold())?;
-                messages.push(superconsole::line!(not));
-            }
-        }
-        Ok(messages)
-    }
+    /// packet sizes in bytes, as a list of values and ranges (e.g. `32..1200+64`)
+    #[argh(option, default = "Spec(vec![50, 100, 200])")]
+    sizes: Spec<usize>,
+
+    /// order of the rate × size matrix: rate-major, size-major or random
+    #[argh(option, default = "Order::RateMajor")]
+    order: Order,
+
+    /// packets per run, 100 unless --duration or --ci-width is given
+    #[argh(option)]
+    count: Option<usize>,
+
+    /// seconds per run; with --count, whichever is reached first ends the run
+    #[argh(option)]
+    duration: Option<f64>,
+
+    /// seconds a reply may take before it counts as missed, the send interval by default
+    #[argh(option)]
+    deadline: Option<f64>,
+
+    /// packets sent at the start of each run that are not counted
+    #[argh(option, default = "0")]
+    warmup: usize,
+
+    /// seconds the uncounted warm-up lasts at least, on top of --warmup packets
+    #[argh(option)]
+    warmup_time: Option<f64>,
+
+    /// seconds to keep collecting late replies after each run
+    #[argh(option)]
+    cooldown: Option<f64>,
+
+    /// highest loss ratio (0 to 1) at which a run passes
+    #[argh(option)]
+    max_loss: Option<f32>,
+
+    /// stop a run as soon as its loss is decided relative to --max-loss, ignoring differences below this margin (e.g. 0.02)
+    #[argh(option)]
+    early_stop: Option<f64>,
+
+    /// keep sending until the 95% confidence interval of the loss is at most this wide (e.g. 0.02)
+    #[argh(option)]
+    ci_width: Option<f64>,
+
+    /// search the highest rate within the --rates range at which each size passes --max-loss
+    #[argh(switch)]
+    search: bool,
+
+    /// search the largest size within the --sizes range that passes --max-loss, at the lowest of --rates
+    #[argh(switch)]
+    size_search: bool,
+
+    /// forbid fragmentation, so datagrams larger than the path MTU are dropped instead
+    #[argh(switch)]
+    dont_fragment: bool,
+
+    /// ramp the rate from the first to the last of --rates over --duration: linear or geometric
+    #[argh(option)]
+    ramp: Option<Shape>,
+
+    /// roll results up into time buckets of this many seconds, 10 for ramps
+    #[argh(option)]
+    bucket: Option<f64>,
+
+    /// when packets are sent: constant, poisson, burst:<packets> or on-off:<period>:<duty>
+    #[argh(option, default = "Model::Constant")]
+    traffic: Model,
+
+    /// seed of the traffic models and the random order, chosen at random by default
+    #[argh(option)]
+    seed: Option<u64>,
+
+    /// send on schedule without waiting for each reply, implied by --traffic models other than constant
+    #[argh(switch)]
+    open_loop: bool,
+
+    /// what fills the probes after their header: const:<byte>, incrementing, random, hex:<bytes> or file:<path>
+    #[argh(option, default = "Payload::default()")]
+    payload: Payload,
+
+    /// check that replies echo the whole probe unchanged, counting truncated and corrupted ones; needs a reflector echoing whole datagrams (serve --full-echo)
+    #[argh(switch)]
+    verify: bool,
+
+    /// replay the timing and sizes of a trace (CSV of `seconds,bytes` or pcap) open-loop, instead of --rates and --sizes
+    #[argh(option)]
+    trace: Option<PathBuf>,
+
+    /// use the 8 byte sequence number format, for reflectors that only echo 8 bytes
+    #[argh(switch)]
+    legacy: bool,
+
+    /// show the median RTT next to the loss in the final rate × size grids
+    #[argh(switch)]
+    grid_rtt: bool,
+
+    /// print plain text results instead of the console, the default when stdout is not a TTY
+    #[argh(switch)]
+    headless: bool,
+
+    /// write the results of every run to this file
+    #[argh(option)]
+    output: Option<PathBuf>,
+
+    /// format of --output: json, ndjson, csv or grid, guessed from the file extension by default
+    #[argh(option)]
+    format: Option<Format>,
+
+    /// write one NDJSON record per packet to this file
+    #[argh(option)]
+    packet_log: Option<PathBuf>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
 }
 
-struct Run<A: ToSocketAddrs> {
-    socket: Arc<UdpSocket>,
-    addr: A,
-    hertz: f32,
-    timeout: Duration,
-    byte_size: usize,
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Serve(serve::ServeArgs),
 }
 
-impl<A: ToSocketAddrs + Clone> Run<A> {
-    pub fn new(socket: Arc<UdpSocket>, addr: A, hertz: f32, byte_size: usize) -> Run<A> {
-        let timeout = Duration::from_secs_f32(1.0 / hertz);
-        Run {
-            socket,
-            addr,
-            hertz,
-            timeout,
-            byte_size,
-        }
-    }
-    fn start(&self) -> impl Stream<Item = Result<bool, Error>> + '_ {
-        stream! {
-            for i in 0..100 {
-                let msg = send_msg(&self.socket, self.addr.clone(), i, self.timeout, self.byte_size).await;
-                yield msg
-            }
-        }
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let args: Args = argh::from_env();
+
+    if let Some(Command::Serve(serve_args)) = args.command {
+        return serve::serve(serve_args).await;
     }
-}
 
-async fn send_msg<A: ToSocketAddrs>(
-    socket: &UdpSocket,
-    addr: A,
-    num: usize,
-    timeout: Duration,
-    byte_size: usize,
-) -> Result<bool, Error> {
-    let window = time::sleep(timeout);
-
-    // build a message of byte_size
-    let mut msg: Vec<u8> = num.to_le_bytes().into();
-    for _ in 0..byte_size - num.to_le_bytes().len() {
-        msg.push(0xff)
+    let bind = args
+        .bind
+        .ok_or_else(|| anyhow::anyhow!("--bind is required"))?;
+    let socket = UdpSocket::bind(bind).await?;
+    if args.dont_fragment {
+        run::set_dont_fragment(&socket).context("setting the don't-fragment option")?;
     }
+    let socket = Arc::new(socket);
+    let session = Session::new(args.legacy);
 
-    let send_recv = async {
-        socket.send_to(&msg, addr).await?;
-        let mut buf = [0; 8];
-        socket.recv(&mut buf).await?;
-        let ret_num = usize::from_le_bytes(buf[0..8].try_into().unwrap());
-        if num != ret_num {
-            anyhow::bail!("oh no");
+    let named = args.plan.is_some();
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let stages = match &args.plan {
+        Some(path) => Plan::load(path)?.stages,
+        None => {
+            let stage = Stage {
+                name: String::new(),
+                targets: vec![],
+                rates: args.rates,
+                sizes: args.sizes,
+                order: args.order,
+                count: args.count,
+                duration: args.duration,
+                deadline: args.deadline,
+                warmup: args.warmup,
+                warmup_time: args.warmup_time,
+                cooldown: args.cooldown,
+                max_loss: args.max_loss,
+                ci_width: args.ci_width,
+                early_stop: args.early_stop,
+                search: args.search,
+                size_search: args.size_search,
+                ramp: args.ramp,
+                bucket: args.bucket,
+                traffic: args.traffic,
+                seed: None,
+                open_loop: args.open_loop,
+                payload: args.payload,
+                verify: args.verify,
+                trace: args.trace,
+            };
+            stage.validate()?;
+            vec![stage]
         }
-        Ok(ret_num)
+    };
+
+    let packet_log = match &args.packet_log {
+        Some(path) => Some(PacketLog::create(path, session)?),
+        None => None,
+    };
+    // a bad stage would otherwise only be noticed after the ones before it ran
+    let mut prepared = vec![];
+    for stage in &stages {
+        let checked = prepare(stage, args.target, session.header_len());
+        prepared.push(match named {
+            true => checked.with_context(|| format!("stage {}", stage.name))?,
+            false => checked?,
+        });
     }
-    .fuse();
-
-    tokio::pin!(window, send_recv);
-    let mut recv = false;
-    loop {
-        tokio::select! {
-            () = &mut window => {
-                return Ok(recv);
-            },
-            _ = &mut send_recv => {
-                recv = true;
+
+    let interrupt = Interrupt::listen().context("listening for signals")?;
+    let mut bench = Bench::new(Ui::new(args.headless, args.grid_rtt), packet_log, interrupt);
+    let outcome: Result<(), Error> = async {
+        for (stage, (targets, trace)) in stages.iter().zip(prepared) {
+            let run = |addr, hertz, byte_size| {
+                let run = Run::new(socket.clone(), session, addr, hertz, byte_size);
+                Run {
+                    dont_fragment: args.dont_fragment,
+                    count: match (stage.count, stage.duration, stage.ci_width) {
+                        (None, Some(_), _) | (None, _, Some(_)) => None,
+                        (count, _, _) => count.or(run.count),
+                    },
+                    duration: stage.duration(),
+                    timeout: stage.deadline().unwrap_or(run.timeout),
+                    warmup: stage.warmup,
+                    warmup_time: stage.warmup_time(),
+                    cooldown: stage.cooldown().unwrap_or(run.cooldown),
+                    max_loss: stage.max_loss,
+                    ci_width: stage.ci_width,
+                    early_stop: stage
+                        .early_stop
+                        .zip(stage.max_loss)
+                        .map(|(margin, max_loss)| Sprt {
+                            threshold: max_loss as f64,
+                            margin,
+                        }),
+                    stage: named.then(|| stage.name.clone()),
+                    // waiting for each reply would undo the model's timing
+                    open_loop: stage.open_loop || stage.traffic != Model::Constant,
+                    bucket: stage.bucket(),
+                    traffic: stage.traffic,
+                    seed: stage.seed.unwrap_or(seed),
+                    verify: stage.verify,
+                    payload: stage.payload.clone(),
+                    ..run
+                }
+            };
+            let max_loss = stage.max_loss.unwrap_or_default();
+            for addr in targets {
+                if let Some(trace) = &trace {
+                    let packets = trace.packets.len().saturating_sub(stage.warmup);
+                    bench
+                        .execute(Run {
+                            count: Some(stage.count.map_or(packets, |c| c.min(packets))),
+                            open_loop: true,
+                            trace: Some(trace.clone()),
+                            ..run(addr, trace.hertz(), trace.max_size())
+                        })
+                        .await?;
+                } else if stage.search {
+                    for &byte_size in &stage.sizes.0 {
+                        let mut search = Search::new(&stage.rates.0);
+                        let mut ids = vec![];
+                        while let Some(hertz) = search.next() {
+                            ids.push(bench.next_id());
+                            let stat = bench.execute(run(addr, hertz, byte_size)).await?;
+                            // a skipped run decided nothing, so it is run again
+                            if !stat.skipped {
+                                search.record(hertz, stat.passed(max_loss));
+                            }
+                        }
+                        let (knee_hz, fail_hz) = search.knee();
+                        bench.add_knee(Knee {
+                            id: 0,
+                            stage: named.then(|| stage.name.clone()),
+                            target: addr,
+                            byte_size,
+                            knee_hz,
+                            fail_hz,
+                            runs: ids,
+                        })?;
+                    }
+                } else if stage.size_search {
+                    let hertz = stage.rates.0.iter().copied().fold(f32::INFINITY, f32::min);
+                    let mut search = SizeSearch::new(&stage.sizes.0);
+                    let mut ids = vec![];
+                    while let Some(byte_size) = search.next() {
+                        ids.push(bench.next_id());
+                        let stat = bench.execute(run(addr, hertz, byte_size)).await?;
+                        if !stat.skipped {
+                            search.record(byte_size, stat.loss(), stat.passed(max_loss));
+                        }
+                    }
+                    let (max_size, fail_size) = search.bounds();
+                    bench.add_max_size(MaxSize {
+                        id: 0,
+                        stage: named.then(|| stage.name.clone()),
+                        target: addr,
+                        hertz,
+                        max_size,
+                        fail_size,
+                        steps: search.steps(),
+                        runs: ids,
+                    })?;
+                } else if let Some(shape) = stage.ramp {
+                    let (from, to) = (stage.rates.0[0], stage.rates.0[stage.rates.0.len() - 1]);
+                    for &byte_size in &stage.sizes.0 {
+                        let ramp = Some(Ramp { shape, to });
+                        bench
+                            .execute(Run {
+                                ramp,
+                                ..run(addr, from, byte_size)
+                            })
+                            .await?;
+                    }
+                } else {
+                    for (hertz, byte_size) in sweep::matrix(
+                        &stage.rates.0,
+                        &stage.sizes.0,
+                        stage.order,
+                        stage.seed.unwrap_or(seed),
+                    ) {
+                        bench.execute(run(addr, hertz, byte_size)).await?;
+                    }
+                }
             }
         }
+        Ok(())
     }
-}
+    .await;
+    let results = bench.finish()?;
+    let interrupted = matches!(&outcome, Err(e) if e.is::<Interrupted>());
+    // any other error stops the bench too, after the results so far are written
+    let error = outcome.err().filter(|_| !interrupted);
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    let args: Args = argh::from_env();
-
-    let addr = args.target;
-    let socket = Arc::new(UdpSocket::bind(args.bind).await?);
-
-    let runs = vec![
-        Run::new(socket.clone(), addr, 4.0, 50),
-        Run::new(socket.clone(), addr, 4.0, 100),
-        Run::new(socket.clone(), addr, 4.0, 200),
-        Run::new(socket.clone(), addr, 8.0, 50),
-        Run::new(socket.clone(), addr, 8.0, 100),
-        Run::new(socket.clone(), addr, 8.0, 200),
-        Run::new(socket.clone(), addr, 16.0, 50),
-        Run::new(socket.clone(), addr, 16.0, 100),
-        Run::new(socket.clone(), addr, 16.0, 200),
-    ];
-
-    let run_components = runs
+    // runs of a search are expected to fail, the search itself fails if nothing passed;
+    // skipped and interrupted runs have no verdict
+    let searched = |idx: &usize| {
+        results.knees.iter().any(|k| k.runs.contains(idx))
+            || results.max_sizes.iter().any(|m| m.runs.contains(idx))
+    };
+    let failed = results
+        .runs
         .iter()
         .enumerate()
-        .map(|(idx, r)| {
-            Box::new(RunComponent {
-                id: idx,
-                hertz: r.hertz,
-                byte_size: r.byte_size,
-            }) as Box<dyn superconsole::Component>
+        .filter(|(idx, r)| match (r.max_loss, results.stats.get(idx)) {
+            (_, Some(stat)) if searched(idx) || stat.is_partial() => false,
+            (Some(max_loss), Some(stat)) => !stat.passed(max_loss),
+            _ => false,
         })
-        .collect();
-
-    let mut console = SuperConsole::new(Box::new(Split::new(
-        run_components,
-        Direction::Vertical,
-        SplitKind::Adaptive,
-    )))
-    .ok_or_else(|| anyhow::anyhow!("Not a TTY"))?;
-
-    let mut state: HashMap<usize, Stat> = Default::default();
-
-    for (idx, r) in runs.iter().enumerate() {
-        let stream = r.start();
-        pin_mut!(stream);
-        while let Some(Ok(msg)) = stream.next().await {
-            let stat = state.entry(idx).or_default();
-            if !msg {
-                stat.missed += 1;
-            }
-            stat.sent += 1;
+        .count();
+    let unbracketed = results.knees.iter().filter(|k| k.knee_hz.is_none()).count()
+        + results
+            .max_sizes
+            .iter()
+            .filter(|m| m.max_size.is_none())
+            .count();
 
-            let state = state!(&state);
-            console.render(&state)?;
-        }
+    if let Some(path) = &args.output {
+        let report = Report {
+            meta: Meta {
+                tool_version: env!("CARGO_PKG_VERSION"),
+                start_time: humantime::format_rfc3339_seconds(session.started).to_string(),
+                bind,
+                default_target: args.target,
+                plan: args.plan.as_ref().map(|p| p.display().to_string()),
+                session: session.id,
+                interrupted,
+                error: error.as_ref().map(|e| format!("{:#}", e)),
+            },
+            runs: results
+                .runs
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, r)| {
+                    let knee = results.knees.iter().find(|k| k.runs.contains(&idx));
+                    let max_size = results.max_sizes.iter().find(|m| m.runs.contains(&idx));
+                    Some(RunResult::new(
+                        idx,
+                        r,
+                        results.stats.get(&idx)?,
+                        knee,
+                        max_size,
+                    ))
+                })
+                .collect(),
+            knees: results.knees.clone(),
+            max_sizes: results.max_sizes.clone(),
+            buckets: results.buckets.clone(),
+            actions: results.actions.clone(),
+            grids: grid::grids(results.runs.iter().enumerate().filter_map(|(idx, r)| {
+                if r.ramp.is_some() || r.trace.is_some() {
+                    return None;
+                }
+                Some(Point {
+                    run: idx,
+                    stage: r.stage.as_deref(),
+                    target: r.addr,
+                    hertz: r.hertz,
+                    byte_size: r.byte_size,
+                    max_loss: r.max_loss,
+                    stat: results.stats.get(&idx)?,
+                })
+            })),
+        };
+        let format = args.format.unwrap_or_else(|| Format::for_path(path));
+        report.write(path, format)?;
+    }
+
+    if let Some(error) = error {
+        return Err(error);
+    }
+    if interrupted {
+        anyhow::bail!("interrupted after {} runs", results.runs.len());
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} runs exceeded their loss threshold",
+            failed,
+            results.runs.len()
+        );
+    }
+    if unbracketed > 0 {
+        anyhow::bail!(
+            "{} of {} searches found nothing within the loss threshold",
+            unbracketed,
+            results.knees.len() + results.max_sizes.len()
+        );
     }
     Ok(())
 }
+
+/// Check what `Stage::validate` can't on its own and load the trace, giving
+/// the targets of the stage and its trace.
+fn prepare(
+    stage: &Stage,
+    default_target: Option<SocketAddr>,
+    header_len: usize,
+) -> Result<(Vec<SocketAddr>, Option<Arc<Trace>>), Error> {
+    if let Some(size) = stage.sizes.0.iter().find(|&&s| s < header_len) {
+        anyhow::bail!(
+            "packet size {} is smaller than the {} byte header",
+            size,
+            header_len
+        );
+    }
+    let targets = match (stage.targets.as_slice(), default_target) {
+        ([], Some(target)) => vec![target],
+        ([], None) => anyhow::bail!("no target given, pass --target"),
+        (targets, _) => targets.to_vec(),
+    };
+    let trace = match &stage.trace {
+        Some(path) => Some(Arc::new(Trace::load(path)?)),
+        None => None,
+    };
+    let sizes = stage.sizes.0.iter().copied();
+    if let Some(largest) = sizes.chain(trace.as_ref().map(|t| t.max_size())).max() {
+        for target in &targets {
+            let max = protocol::max_probe_len(target);
+            if largest > max {
+                anyhow::bail!(
+                    "packet size {} is larger than the {} bytes a datagram to {} can carry",
+                    largest,
+                    max,
+                    target
+                );
+            }
+        }
+    }
+    Ok((targets, trace))
+}
@@ -0,0 +1,68 @@
+use crate::{
+    run::Session,
+    stat::ms,
+    track::{Damage, Event, Outcome},
+};
+use anyhow::{Context, Error};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+};
+
+/// Writes one NDJSON record per packet event.
+///
+/// A probe has a single record, except for a late reply, which follows the
+/// `missed` record of its probe with a `late` record of its own.
+pub struct PacketLog {
+    out: BufWriter<File>,
+    session: Session,
+}
+
+#[derive(Serialize)]
+struct Record {
+    run: usize,
+    /// `None` for malformed replies
+    seq: Option<usize>,
+    outcome: Outcome,
+    sent: Option<String>,
+    received: Option<String>,
+    rtt_ms: Option<f64>,
+    from: Option<SocketAddr>,
+    damage: Option<Damage>,
+}
+
+impl PacketLog {
+    pub fn create(path: &Path, session: Session) -> Result<PacketLog, Error> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        Ok(PacketLog {
+            out: BufWriter::new(file),
+            session,
+        })
+    }
+
+    pub fn write(&mut self, run: usize, event: &Event) -> Result<(), Error> {
+        let timestamp =
+            |at| humantime::format_rfc3339_micros(self.session.wall_clock(at)).to_string();
+        let record = Record {
+            run,
+            seq: (event.outcome != Outcome::Malformed).then_some(event.seq),
+            outcome: event.outcome,
+            sent: event.sent_at.map(timestamp),
+            received: event.reply.map(|reply| timestamp(reply.at)),
+            rtt_ms: event.rtt().map(ms),
+            from: event.reply.map(|reply| reply.from),
+            damage: event.reply.and_then(|reply| reply.damage),
+        };
+        serde_json::to_writer(&mut self.out, &record)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// What fills the probes after their header.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Payload {
+    /// the same byte throughout
+    Constant(u8),
+    /// 0, 1, 2, … wrapping at 255
+    Incrementing,
+    /// pseudo-random bytes, seeded per probe by the run seed, id and sequence
+    Random,
+    /// these bytes, repeated, read from a file or given as hex
+    Bytes { source: Source, bytes: Arc<[u8]> },
+}
+
+/// Where the bytes of a `Payload::Bytes` came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Hex,
+    File(String),
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload::Constant(0xff)
+    }
+}
+
+impl Payload {
+    /// Fill `buf` with the payload of a probe, `seed` tells the probes of a
+    /// random payload apart.
+    pub fn fill(&self, seed: u64, buf: &mut [u8]) {
+        match self {
+            Payload::Constant(byte) => buf.fill(*byte),
+            Payload::Incrementing => {
+                for (i, b) in buf.iter_mut().enumerate() {
+                    *b = i as u8;
+                }
+            }
+            Payload::Random => StdRng::seed_from_u64(seed).fill_bytes(buf),
+            Payload::Bytes { bytes, .. } => {
+                for (b, &v) in buf.iter_mut().zip(bytes.iter().cycle()) {
+                    *b = v;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Payload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let payload = match s.split_once(':') {
+            None if s == "incrementing" => Payload::Incrementing,
+            None if s == "random" => Payload::Random,
+            Some(("const", byte)) => {
+                let parsed = match byte.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => byte.parse(),
+                };
+                Payload::Constant(parsed.map_err(|e| format!("payload byte `{}`: {}", byte, e))?)
+            }
+            Some(("hex", hex)) => Payload::Bytes {
+                source: Source::Hex,
+                bytes: parse_hex(hex)?.into(),
+            },
+            Some(("file", path)) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|e| format!("reading payload file {}: {}", path, e))?;
+                if bytes.is_empty() {
+                    return Err(format!("payload file {} is empty", path));
+                }
+                Payload::Bytes {
+                    source: Source::File(path.to_owned()),
+                    bytes: bytes.into(),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "unknown payload `{}`, expected const:<byte>, incrementing, random, \
+                     hex:<bytes> or file:<path>",
+                    s
+                ))
+            }
+        };
+        Ok(payload)
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!(
+            "payload hex `{}` needs a positive, even number of digits",
+            hex
+        ));
+    }
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("payload hex `{}` has a non-hex digit", hex));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|e| format!("payload hex `{}`: {}", hex, e))
+        })
+        .collect()
+}
+
+impl TryFrom<String> for Payload {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Constant(byte) => write!(f, "const:0x{:02x}", byte),
+            Payload::Incrementing => write!(f, "incrementing"),
+            Payload::Random => write!(f, "random"),
+            Payload::Bytes {
+                source: Source::Hex,
+                bytes,
+            } => {
+                write!(f, "hex:")?;
+                bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            Payload::Bytes {
+                source: Source::File(path),
+                ..
+            } => write!(f, "file:{}", path),
+        }
+    }
+}
+
+impl Serialize for Payload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(payload: &str, seed: u64) -> Vec<u8> {
+        let mut buf = vec![0; 6];
+        payload.parse::<Payload>().unwrap().fill(seed, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn parses_payloads() {
+        for payload in ["const:0x00", "incrementing", "random", "hex:deadbeef"] {
+            assert_eq!(payload.parse::<Payload>().unwrap().to_string(), payload);
+        }
+        assert_eq!("const:255".parse(), Ok(Payload::Constant(0xff)));
+        for bad in [
+            "const:256",
+            "hex:abc",
+            "hex:",
+            "hex:zz",
+            "hex:é1",
+            "file:/nonexistent",
+            "zeros",
+        ] {
+            assert!(bad.parse::<Payload>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn fills_patterns() {
+        assert_eq!(filled("const:7", 0), vec![7; 6]);
+        assert_eq!(filled("incrementing", 0), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(
+            filled("hex:0xa0b1", 0),
+            vec![0xa0, 0xb1, 0xa0, 0xb1, 0xa0, 0xb1]
+        );
+        assert_eq!(filled("random", 1), filled("random", 1));
+        assert_ne!(filled("random", 1), filled("random", 2));
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    payload::Payload,
+    profile::{Model, Shape},
+    sweep::{Order, Spec},
+};
+use anyhow::{Context, Error};
+use serde::Deserialize;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Length of the time buckets of a ramp unless set.
+const DEFAULT_BUCKET: Duration = Duration::from_secs(10);
+
+/// Longest time a stage setting may take, a year, so instants it is added to
+/// can't overflow.
+const MAX_SECS: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A test plan, a list of named stages run one after the other.
+///
+/// ```toml
+/// [[stage]]
+/// name = "smoke"
+/// targets = ["[fd00:1eaf::a08d:cfd3:fffe:bde1]:2001"]
+/// rates = "4..16*2"
+/// sizes = "50,100"
+/// count = 50
+/// warmup = 5
+/// cooldown = 1.5
+/// max_loss = 0.05
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    #[serde(rename = "stage")]
+    pub stages: Vec<Stage>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Plan, Error> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading plan {}", path.display()))?;
+        let mut table: toml::Table =
+            toml::from_str(&text).with_context(|| format!("parsing plan {}", path.display()))?;
+        if let Some(dir) = path.parent() {
+            resolve_paths(&mut table, dir);
+        }
+        let plan: Plan = table
+            .try_into()
+            .with_context(|| format!("parsing plan {}", path.display()))?;
+        if plan.stages.is_empty() {
+            anyhow::bail!("plan {} has no stages", path.display());
+        }
+        for stage in &plan.stages {
+            stage
+                .validate()
+                .with_context(|| format!("stage {}", stage.name))?;
+        }
+        Ok(plan)
+    }
+}
+
+/// Make the trace and payload file paths of every stage relative to `dir`,
+/// that of the plan, rather than to the working directory.
+fn resolve_paths(plan: &mut toml::Table, dir: &Path) {
+    let stages = match plan.get_mut("stage") {
+        Some(toml::Value::Array(stages)) => stages,
+        _ => return,
+    };
+    let resolve = |path: &str| dir.join(path).display().to_string();
+    for stage in stages.iter_mut().filter_map(toml::Value::as_table_mut) {
+        if let Some(toml::Value::String(trace)) = stage.get_mut("trace") {
+            *trace = resolve(trace);
+        }
+        if let Some(toml::Value::String(payload)) = stage.get_mut("payload") {
+            if let Some(file) = payload.strip_prefix("file:") {
+                *payload = format!("file:{}", resolve(file));
+            }
+        }
+    }
+}
+
+/// A stage of a plan, expanded into one `Run` per target, rate and size, or
+/// one per target replaying a trace.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Stage {
+    pub name: String,
+    /// targets of the stage, `--target` when empty
+    #[serde(default)]
+    pub targets: Vec<SocketAddr>,
+    /// required unless the stage replays a trace
+    #[serde(default)]
+    pub rates: Spec<f32>,
+    #[serde(default)]
+    pub sizes: Spec<usize>,
+    #[serde(default)]
+    pub order: Order,
+    /// packets per run, 100 unless a duration or ci_width is given
+    pub count: Option<usize>,
+    /// seconds per run, whichever of count and duration is reached first ends it
+    pub duration: Option<f64>,
+    /// seconds a reply may take, the send interval by default
+    pub deadline: Option<f64>,
+    /// packets sent at the start of each run that are not counted
+    #[serde(default)]
+    pub warmup: usize,
+    /// seconds the warm-up lasts at least, on top of `warmup` packets
+    pub warmup_time: Option<f64>,
+    /// seconds to keep collecting late replies after each run
+    pub cooldown: Option<f64>,
+    /// highest loss ratio (0 to 1) at which a run passes
+    pub max_loss: Option<f32>,
+    /// keep sending until the 95% confidence interval of the loss is at most
+    /// this wide, count and duration still bound the run when given
+    pub ci_width: Option<f64>,
+    /// stop a run as soon as its loss is decided relative to `max_loss`,
+    /// ignoring differences below this margin
+    pub early_stop: Option<f64>,
+    /// search the highest rate within `rates` at which each size passes
+    /// `max_loss`, instead of running every rate
+    #[serde(default)]
+    pub search: bool,
+    /// search the largest size within `sizes` that passes `max_loss`, at
+    /// the lowest of `rates`, instead of running every size
+    #[serde(default)]
+    pub size_search: bool,
+    /// ramp the rate from the first to the last of `rates` over `duration`,
+    /// one run per target and size
+    pub ramp: Option<Shape>,
+    /// seconds per time bucket the runs are rolled up into, 10 for ramps
+    pub bucket: Option<f64>,
+    #[serde(default)]
+    pub traffic: Model,
+    /// seeds the traffic model and the random order, `--seed` by default
+    pub seed: Option<u64>,
+    /// send on schedule without waiting for each reply, implied by a traffic
+    /// model other than constant
+    #[serde(default)]
+    pub open_loop: bool,
+    #[serde(default)]
+    pub payload: Payload,
+    /// check that replies echo the whole probe unchanged
+    #[serde(default)]
+    pub verify: bool,
+    /// CSV or pcap file whose timing and sizes are replayed open-loop,
+    /// instead of `rates` and `sizes`
+    pub trace: Option<PathBuf>,
+}
+
+impl Stage {
+    /// Check the settings that are not constrained by their type.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.count == Some(0) {
+            anyhow::bail!("count must be positive");
+        }
+        if !self.duration.is_none_or(|d| d > 0.0 && is_secs(d)) {
+            anyhow::bail!("duration must be a positive number of seconds, at most a year");
+        }
+        if !self.deadline.is_none_or(|d| d > 0.0 && is_secs(d)) {
+            anyhow::bail!("deadline must be a positive number of seconds, at most a year");
+        }
+        if !self.warmup_time.is_none_or(is_secs) {
+            anyhow::bail!("warmup_time must be a number of seconds up to a year");
+        }
+        if !self.cooldown.is_none_or(is_secs) {
+            anyhow::bail!("cooldown must be a number of seconds up to a year");
+        }
+        if !self.max_loss.is_none_or(|l| (0.0..=1.0).contains(&l)) {
+            anyhow::bail!("max_loss must be between 0 and 1");
+        }
+        if !self.early_stop.is_none_or(|m| m > 0.0 && m < 1.0) {
+            anyhow::bail!("early_stop must be between 0 and 1");
+        }
+        match (self.early_stop, self.max_loss) {
+            (Some(_), None) => anyhow::bail!("early_stop needs a max_loss to decide against"),
+            // a margin reaching 0 or 1 would decide on the first packet
+            (Some(margin), Some(max_loss))
+                if margin as f32 >= max_loss || max_loss + margin as f32 >= 1.0 =>
+            {
+                anyhow::bail!("early_stop must be below both max_loss and 1 - max_loss")
+            }
+            _ => {}
+        }
+        if self.search && self.max_loss.is_none() {
+            anyhow::bail!("search needs a max_loss to search against");
+        }
+        if self.size_search && self.max_loss.is_none() {
+            anyhow::bail!("size_search needs a max_loss to search against");
+        }
+        if self.search && self.size_search {
+            anyhow::bail!("search and size_search can't be combined");
+        }
+        if self.ramp.is_some() && self.duration.is_none() {
+            anyhow::bail!("ramp needs a duration to ramp over");
+        }
+        if self.ramp.is_some() && (self.search || self.size_search) {
+            anyhow::bail!("ramp can't be combined with a search");
+        }
+        if !self.bucket.is_none_or(|b| b > 0.0 && is_secs(b)) {
+            anyhow::bail!("bucket must be a positive number of seconds, at most a year");
+        }
+        if !self.ci_width.is_none_or(|w| w > 0.0 && w < 1.0) {
+            anyhow::bail!("ci_width must be between 0 and 1");
+        }
+        if self.trace.is_none() && (self.rates.0.is_empty() || self.sizes.0.is_empty()) {
+            anyhow::bail!("rates and sizes are required unless a trace is replayed");
+        }
+        if self.trace.is_some()
+            && (self.search || self.size_search || self.ramp.is_some() || self.ci_width.is_some())
+        {
+            anyhow::bail!("a trace can't be combined with a search, ramp or ci_width");
+        }
+        if self.trace.is_some() && self.traffic != Model::Constant {
+            anyhow::bail!("a trace replays its own timing, it can't have a traffic model");
+        }
+        Ok(())
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration.map(Duration::from_secs_f64)
+    }
+
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline.map(Duration::from_secs_f64)
+    }
+
+    pub fn warmup_time(&self) -> Option<Duration> {
+        self.warmup_time.map(Duration::from_secs_f64)
+    }
+
+    pub fn cooldown(&self) -> Option<Duration> {
+        self.cooldown.map(Duration::from_secs_f64)
+    }
+
+    pub fn bucket(&self) -> Option<Duration> {
+        match (self.bucket, self.ramp) {
+            (Some(secs), _) => Some(Duration::from_secs_f64(secs)),
+            (None, Some(_)) => Some(DEFAULT_BUCKET),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Whether `secs` converts to a `Duration` of at most `MAX_SECS`, so it isn't
+/// negative, NaN or too large.
+fn is_secs(secs: f64) -> bool {
+    Duration::try_from_secs_f64(secs).is_ok_and(|d| d <= MAX_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<Stage, Error> {
+        let plan: Plan = toml::from_str(&format!("[[stage]]\nname = \"test\"\n{}", toml))?;
+        let stage = plan.stages.into_iter().next().unwrap();
+        stage.validate()?;
+        Ok(stage)
+    }
+
+    #[test]
+    fn parses_stages() {
+        let stage = parse(
+            r#"
+            targets = ["[fd00:1eaf::a08d:cfd3:fffe:bde1]:2001"]
+            rates = "4..16*2"
+            sizes = "50,100"
+            count = 50
+            cooldown = 1.5
+            max_loss = 0.05
+            "#,
+        )
+        .unwrap();
+        assert_eq!(stage.rates.0, vec![4.0, 8.0, 16.0]);
+        assert_eq!(stage.sizes.0, vec![50, 100]);
+        assert_eq!(stage.count, Some(50));
+        assert_eq!(stage.cooldown(), Some(Duration::from_millis(1500)));
+        assert_eq!(stage.order, Order::RateMajor);
+        assert!(parse("rates = \"4\"\nsizes = \"50\"\ncolour = \"red\"").is_err());
+        assert!(toml::from_str::<Plan>("stages = []").is_err());
+    }
+
+    #[test]
+    fn resolves_paths_next_to_the_plan() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            [[stage]]
+            trace = "traces/gateway.pcap"
+            payload = "file:frame.bin"
+            [[stage]]
+            trace = "/var/traces/gateway.csv"
+            payload = "random"
+            "#,
+        )
+        .unwrap();
+        resolve_paths(&mut table, Path::new("plans"));
+        let stages = table["stage"].as_array().unwrap();
+        let field = |stage: usize, key| stages[stage][key].as_str().unwrap();
+        assert_eq!(field(0, "trace"), "plans/traces/gateway.pcap");
+        assert_eq!(field(0, "payload"), "file:plans/frame.bin");
+        assert_eq!(field(1, "trace"), "/var/traces/gateway.csv");
+        assert_eq!(field(1, "payload"), "random");
+    }
+
+    #[test]
+    fn validates_stages() {
+        for bad in [
+            "count = 1",
+            "rates = \"4\"\nsizes = \"50\"\ncount = 0",
+            "rates = \"4\"\nsizes = \"50\"\nmax_loss = 1.5",
+            "rates = \"4\"\nsizes = \"50\"\nmax_loss = 0.05\nearly_stop = 0.05",
+            "rates = \"4\"\nsizes = \"50\"\nmax_loss = 0.9\nearly_stop = 0.1",
+            "rates = \"4\"\nsizes = \"50\"\nsearch = true",
+            "rates = \"4\"\nsizes = \"50\"\nmax_loss = 0.1\nsearch = true\nsize_search = true",
+            "rates = \"4\"\nsizes = \"50\"\nramp = \"linear\"",
+            "trace = \"t.csv\"\nmax_loss = 0.1\nsearch = true",
+            "trace = \"t.csv\"\nmax_loss = 0.1\nsize_search = true",
+            "trace = \"t.csv\"\nduration = 10\nramp = \"linear\"",
+            "trace = \"t.csv\"\nci_width = 0.1",
+            "trace = \"t.csv\"\ntraffic = \"poisson\"",
+            "trace = \"t.csv\"\nduration = 1e20",
+            "trace = \"t.csv\"\ndeadline = inf",
+            "trace = \"t.csv\"\nwarmup_time = -1",
+            "trace = \"t.csv\"\ncooldown = 1e300",
+            "trace = \"t.csv\"\nbucket = 0",
+        ] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+        assert!(parse("trace = \"t.csv\"\ncount = 10").is_ok());
+        assert!(parse("rates = \"4\"\nsizes = \"50\"\nmax_loss = 0.05\nearly_stop = 0.02").is_ok());
+        assert!(parse("rates = \"1..8\"\nsizes = \"50\"\nmax_loss = 0.1\nsearch = true").is_ok());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::trace::Trace;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+
+/// How the rate of a ramp moves from its first to its last value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", rename_all = "snake_case")]
+pub enum Shape {
+    /// by the same number of hertz every second
+    Linear,
+    /// by the same factor every second
+    Geometric,
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Shape::Linear),
+            "geometric" => Ok(Shape::Geometric),
+            _ => Err(format!(
+                "unknown ramp `{}`, expected linear or geometric",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shape::Linear => write!(f, "linear"),
+            Shape::Geometric => write!(f, "geometric"),
+        }
+    }
+}
+
+impl TryFrom<String> for Shape {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// When the packets of a run are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Model {
+    /// evenly, one every `1/hertz`
+    #[default]
+    Constant,
+    /// at exponentially distributed intervals
+    Poisson,
+    /// in back-to-back bursts of this many packets, one burst every
+    /// `packets/hertz`
+    Burst(usize),
+    /// evenly, but only during the first `duty` of every `period` seconds
+    OnOff { period: f64, duty: f64 },
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let model = match (parts.next(), parts.next(), parts.next()) {
+            (Some("constant"), None, None) => Model::Constant,
+            (Some("poisson"), None, None) => Model::Poisson,
+            (Some("burst"), Some(packets), None) => {
+                let packets = packets
+                    .parse()
+                    .map_err(|e| format!("burst size `{}`: {}", packets, e))?;
+                if packets == 0 {
+                    return Err("burst size must be positive".to_owned());
+                }
+                Model::Burst(packets)
+            }
+            (Some("on-off"), Some(period), Some(duty)) => {
+                let period: f64 = period
+                    .parse()
+                    .map_err(|e| format!("on-off period `{}`: {}", period, e))?;
+                let duty: f64 = duty
+                    .parse()
+                    .map_err(|e| format!("on-off duty cycle `{}`: {}", duty, e))?;
+                if !(period.is_finite() && period > 0.0) {
+                    return Err("on-off period must be positive".to_owned());
+                }
+                if !(duty > 0.0 && duty <= 1.0) {
+                    return Err("on-off duty cycle must be above 0 and at most 1".to_owned());
+                }
+                Model::OnOff { period, duty }
+            }
+            _ => {
+                return Err(format!(
+                    "unknown traffic model `{}`, expected constant, poisson, \
+                     burst:<packets> or on-off:<period>:<duty>",
+                    s
+                ))
+            }
+        };
+        if parts.next().is_some() {
+            return Err(format!("unknown traffic model `{}`", s));
+        }
+        Ok(model)
+    }
+}
+
+impl TryFrom<String> for Model {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Constant => write!(f, "constant"),
+            Model::Poisson => write!(f, "poisson"),
+            Model::Burst(packets) => write!(f, "burst:{}", packets),
+            Model::OnOff { period, duty } => write!(f, "on-off:{}:{}", period, duty),
+        }
+    }
+}
+
+impl Serialize for Model {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A rate that moves to `to` hertz over the duration of a run.
+#[derive(Debug, Clone, Copy)]
+pub struct Ramp {
+    pub shape: Shape,
+    pub to: f32,
+}
+
+/// The send rate of a run over time.
+#[derive(Debug, Clone)]
+pub struct Pace {
+    pub hertz: f32,
+    pub ramp: Option<Ramp>,
+    /// how long the ramp takes
+    pub over: Option<Duration>,
+    pub model: Model,
+    /// recorded timing replayed instead of the model
+    pub trace: Option<Arc<Trace>>,
+}
+
+impl Pace {
+    /// Rate `since` into the measured part of the run.
+    pub fn hertz_at(&self, since: Duration) -> f32 {
+        let (ramp, over) = match (self.ramp, self.over) {
+            (Some(ramp), Some(over)) => (ramp, over),
+            _ => return self.hertz,
+        };
+        let progress = (since.as_secs_f32() / over.as_secs_f32()).min(1.0);
+        match ramp.shape {
+            Shape::Linear => self.hertz + (ramp.to - self.hertz) * progress,
+            Shape::Geometric => self.hertz * (ramp.to / self.hertz).powf(progress),
+        }
+    }
+}
+
+/// Spaces the packets of a run according to its pace.
+pub struct Pacer {
+    pace: Pace,
+    rng: StdRng,
+    /// packets sent of the current burst
+    burst: usize,
+    /// packets sent so far
+    sent: usize,
+}
+
+impl Pacer {
+    pub fn new(pace: Pace, seed: u64) -> Pacer {
+        Pacer {
+            pace,
+            rng: StdRng::seed_from_u64(seed),
+            burst: 0,
+            sent: 0,
+        }
+    }
+
+    /// Average time between packets `since` into the measured part.
+    pub fn interval(&self, since: Duration) -> Duration {
+        Duration::from_secs_f64(1.0 / self.pace.hertz_at(since) as f64)
+    }
+
+    /// Whether every packet of the trace has been sent.
+    pub fn is_done(&self) -> bool {
+        self.pace
+            .trace
+            .as_ref()
+            .is_some_and(|t| self.sent >= t.packets.len())
+    }
+
+    /// Time until the packet after one sent `since` into the measured part.
+    pub fn gap(&mut self, since: Duration) -> Duration {
+        self.sent += 1;
+        if let Some(trace) = &self.pace.trace {
+            return trace.gap(self.sent);
+        }
+        let interval = self.interval(since).as_secs_f64();
+        let gap = match self.pace.model {
+            Model::Constant => interval,
+            Model::Poisson => {
+                // inverse transform of a uniform sample in (0, 1]
+                let u: f64 = 1.0 - self.rng.gen::<f64>();
+                -u.ln() * interval
+            }
+            Model::Burst(packets) => {
+                self.burst += 1;
+                if self.burst < packets {
+                    0.0
+                } else {
+                    self.burst = 0;
+                    interval * packets as f64
+                }
+            }
+            Model::OnOff { period, duty } => {
+                let next = since.as_secs_f64() + interval;
+                let phase = next % period;
+                if phase < period * duty {
+                    interval
+                } else {
+                    interval + period - phase
+                }
+            }
+        };
+        Duration::from_secs_f64(gap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pacer(model: Model, seed: u64) -> Pacer {
+        let pace = Pace {
+            hertz: 10.0,
+            ramp: None,
+            over: None,
+            model,
+            trace: None,
+        };
+        Pacer::new(pace, seed)
+    }
+
+    /// Send times of the first `n` packets.
+    fn schedule(pacer: &mut Pacer, n: usize) -> Vec<f64> {
+        let mut since = Duration::ZERO;
+        (0..n)
+            .map(|_| {
+                let at = since.as_secs_f64();
+                since += pacer.gap(since);
+                at
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_models() {
+        for model in ["constant", "poisson", "burst:8", "on-off:10:0.25"] {
+            assert_eq!(model.parse::<Model>().unwrap().to_string(), model);
+        }
+        for bad in [
+            "burst",
+            "burst:0",
+            "on-off:10",
+            "on-off:10:2",
+            "poisson:1",
+            "pareto",
+        ] {
+            assert!(bad.parse::<Model>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn poisson_is_seeded() {
+        let a = schedule(&mut pacer(Model::Poisson, 7), 1000);
+        let b = schedule(&mut pacer(Model::Poisson, 7), 1000);
+        let c = schedule(&mut pacer(Model::Poisson, 8), 1000);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // 1000 packets at 10hz take about 100 seconds
+        assert!((a[999] - 100.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn bursts_keep_the_rate() {
+        let times = schedule(&mut pacer(Model::Burst(4), 0), 9);
+        assert_eq!(&times[..4], &[0.0; 4]);
+        assert!((times[4] - 0.4).abs() < 1e-9);
+        assert!((times[8] - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn on_off_sends_during_on() {
+        let model = Model::OnOff {
+            period: 1.0,
+            duty: 0.5,
+        };
+        let times = schedule(&mut pacer(model, 0), 20);
+        assert!(times.iter().all(|t| t % 1.0 < 0.5 + 1e-9));
+        assert!((times[5] - 1.0).abs() < 1e-9);
+    }
+}
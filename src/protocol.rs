@@ -0,0 +1,179 @@
+//! Probe wire format.
+//!
+//! Every probe starts with a fixed header, followed by padding up to the
+//! packet size. Reflectors echo the header back unchanged. All fields are in
+//! network byte order.
+//!
+//! ```text
+//!  0       4       5       6       8              12             16
+//!  +-------+-------+-------+-------+--------------+--------------+
+//!  | magic |version| flags |   0   |  session id  |    run id    |
+//!  +-------+-------+-------+-------+--------------+--------------+
+//!  |           sequence            |    send timestamp (µs)      |
+//!  +-------------------------------+-----------------------------+
+//!  16                              24                            32
+//! ```
+//!
+//! The session id is chosen at random by every tool instance, the run id is
+//! the index of the run, and the timestamp counts microseconds since the
+//! session started. Replies of another session or run can therefore never be
+//! mistaken for replies of the current run.
+//!
+//! Reflectors that only echo 8 bytes can be driven with the legacy format,
+//! which is just the sequence number as a little endian `u64`.
+
+use std::{convert::TryInto, fmt, net::SocketAddr};
+
+pub const MAGIC: [u8; 4] = *b"AeTP";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 32;
+pub const LEGACY_HEADER_LEN: usize = 8;
+
+/// Header flags.
+pub mod flags {
+    /// The probe belongs to a run's warm-up and is not counted.
+    pub const WARMUP: u8 = 1 << 0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub flags: u8,
+    pub session: u32,
+    pub run: u32,
+    pub seq: u64,
+    /// microseconds since the session started
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    Short(usize),
+    Magic,
+    Version(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Short(len) => write!(f, "{} bytes is shorter than a header", len),
+            DecodeError::Magic => write!(f, "bad magic"),
+            DecodeError::Version(v) => write!(f, "unsupported version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Header {
+    /// Write the header into the first `HEADER_LEN` bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) {
+        let buf = &mut buf[..HEADER_LEN];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        buf[5] = self.flags;
+        buf[6..8].copy_from_slice(&[0, 0]);
+        buf[8..12].copy_from_slice(&self.session.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.run.to_be_bytes());
+        buf[16..24].copy_from_slice(&self.seq.to_be_bytes());
+        buf[24..32].copy_from_slice(&self.timestamp.to_be_bytes());
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Header, DecodeError> {
+        if buf.len() < HEADER_LEN {
+            return Err(DecodeError::Short(buf.len()));
+        }
+        if buf[0..4] != MAGIC {
+            return Err(DecodeError::Magic);
+        }
+        if buf[4] != VERSION {
+            return Err(DecodeError::Version(buf[4]));
+        }
+        Ok(Header {
+            flags: buf[5],
+            session: u32::from_be_bytes(buf[8..12].try_into().unwrap()),
+            run: u32::from_be_bytes(buf[12..16].try_into().unwrap()),
+            seq: u64::from_be_bytes(buf[16..24].try_into().unwrap()),
+            timestamp: u64::from_be_bytes(buf[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+/// Sequence number of a legacy probe.
+pub fn decode_legacy(buf: &[u8]) -> Result<u64, DecodeError> {
+    match buf.get(..LEGACY_HEADER_LEN) {
+        Some(seq) => Ok(u64::from_le_bytes(seq.try_into().unwrap())),
+        None => Err(DecodeError::Short(buf.len())),
+    }
+}
+
+/// Largest probe that fits a UDP datagram to `addr`, without IPv6
+/// jumbograms. Replies this long still fit the receive buffer of `u16::MAX`.
+pub fn max_probe_len(addr: &SocketAddr) -> usize {
+    match addr {
+        SocketAddr::V4(_) => 65_507,
+        SocketAddr::V6(_) => 65_527,
+    }
+}
+
+pub fn encode_legacy(seq: u64, buf: &mut [u8]) {
+    buf[..LEGACY_HEADER_LEN].copy_from_slice(&seq.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            flags: flags::WARMUP,
+            session: 0xdead_beef,
+            run: 7,
+            seq: u64::MAX - 1,
+            timestamp: 1_234_567,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = vec![0xff; 50];
+        header().encode(&mut buf);
+        assert_eq!(Header::decode(&buf), Ok(header()));
+        assert_eq!(Header::decode(&buf[..HEADER_LEN]), Ok(header()));
+        assert!(buf[HEADER_LEN..].iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn layout() {
+        let mut buf = [0; HEADER_LEN];
+        header().encode(&mut buf);
+        assert_eq!(&buf[0..4], b"AeTP");
+        assert_eq!(buf[4], VERSION);
+        assert_eq!(buf[5], flags::WARMUP);
+        assert_eq!(&buf[8..12], &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(&buf[12..16], &[0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let mut buf = [0; HEADER_LEN];
+        header().encode(&mut buf);
+        assert_eq!(Header::decode(&buf[..8]), Err(DecodeError::Short(8)));
+
+        let mut bad = buf;
+        bad[0] = b'X';
+        assert_eq!(Header::decode(&bad), Err(DecodeError::Magic));
+
+        let mut bad = buf;
+        bad[4] = VERSION + 1;
+        assert_eq!(Header::decode(&bad), Err(DecodeError::Version(VERSION + 1)));
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let mut buf = [0xff; 20];
+        encode_legacy(42, &mut buf);
+        assert_eq!(decode_legacy(&buf), Ok(42));
+        assert_eq!(&buf[..8], &42u64.to_le_bytes());
+        assert_eq!(decode_legacy(&buf[..4]), Err(DecodeError::Short(4)));
+    }
+}
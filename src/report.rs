@@ -0,0 +1,399 @@
+use crate::{
+    grid::Grid,
+    keys::Action,
+    payload::Payload,
+    profile::{Model, Shape},
+    rollup::Bucket,
+    run::Run,
+    search::{Knee, MaxSize},
+    stat::{ms, Decision, Latency, Stat},
+};
+use anyhow::{Context, Error};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+/// File format of the results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// a single document with the metadata and a list of runs
+    Json,
+    /// one object per run, time bucket and console action, each carrying
+    /// the metadata
+    Ndjson,
+    /// one row per run, or per time bucket if runs were rolled up, each
+    /// carrying the metadata
+    Csv,
+    /// the loss grids as CSV, a row per stage, target and rate and a column
+    /// per size
+    Grid,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "grid" => Ok(Format::Grid),
+            _ => Err(format!(
+                "unknown format `{}`, expected json, ndjson, csv or grid",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// The format matching the extension of `path`, JSON if there is none.
+    pub fn for_path(path: &Path) -> Format {
+        path.extension()
+            .and_then(|ext| ext.to_str()?.parse().ok())
+            .unwrap_or(Format::Json)
+    }
+}
+
+/// Describes the invocation the results belong to.
+#[derive(Debug, Serialize)]
+pub struct Meta {
+    pub tool_version: &'static str,
+    pub start_time: String,
+    pub bind: SocketAddr,
+    /// `--target`, used by runs without a target of their own
+    pub default_target: Option<SocketAddr>,
+    pub plan: Option<String>,
+    pub session: u32,
+    /// the bench was interrupted, the last run is partial
+    pub interrupted: bool,
+    /// the error that stopped the bench before its last stage was done
+    pub error: Option<String>,
+}
+
+/// Configuration and results of a single run.
+#[derive(Debug, Serialize)]
+pub struct RunResult {
+    pub id: usize,
+    pub stage: Option<String>,
+    pub target: SocketAddr,
+    /// mean rate of a replayed trace
+    pub hertz: f32,
+    /// largest packet of a replayed trace
+    pub byte_size: usize,
+    pub count: Option<usize>,
+    pub duration_secs: Option<f64>,
+    pub deadline_secs: f64,
+    pub warmup: usize,
+    pub warmup_secs: Option<f64>,
+    pub cooldown_secs: f64,
+    pub open_loop: bool,
+    pub ramp: Option<Shape>,
+    /// rate at the end of the ramp, `hertz` is the rate at its start
+    pub ramp_to_hz: Option<f32>,
+    pub traffic: Model,
+    pub seed: u64,
+    /// file whose timing and sizes were replayed
+    pub trace: Option<String>,
+    pub payload: Payload,
+    pub sent: usize,
+    pub missed: usize,
+    pub lost: usize,
+    pub late: usize,
+    pub reordered: usize,
+    pub duplicate: usize,
+    pub unknown: usize,
+    /// replies without a decodable header
+    pub malformed: usize,
+    /// whether replies were checked against their probe
+    pub verify: bool,
+    pub truncated: usize,
+    pub corrupted: usize,
+    pub loss: f32,
+    /// ratio of probes missed or answered by a damaged reply, which
+    /// `max_loss` applies to
+    pub failure: f32,
+    pub loss_ci_low: Option<f64>,
+    pub loss_ci_high: Option<f64>,
+    pub ci_width: Option<f64>,
+    pub max_loss: Option<f32>,
+    /// `None` without a `max_loss` or for a skipped or interrupted run
+    pub passed: Option<bool>,
+    pub early_stop_margin: Option<f64>,
+    /// side of `max_loss` the early-stop test decided for
+    pub decision: Option<Decision>,
+    pub stopped_early: bool,
+    /// cut short by an interrupt
+    pub interrupted: bool,
+    /// ended early from the console
+    pub skipped: bool,
+    /// id of the rate search the run was part of
+    pub search: Option<usize>,
+    /// highest passing rate found by that search
+    pub knee_hz: Option<f32>,
+    /// id of the size search the run was part of
+    pub size_search: Option<usize>,
+    /// largest passing size found by that search
+    pub max_size: Option<usize>,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_mean_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    pub rtt_stddev_ms: Option<f64>,
+    pub rtt_p50_ms: Option<f64>,
+    pub rtt_p90_ms: Option<f64>,
+    pub rtt_p99_ms: Option<f64>,
+    pub rtt_p999_ms: Option<f64>,
+}
+
+impl RunResult {
+    pub fn new(
+        id: usize,
+        run: &Run<SocketAddr>,
+        stat: &Stat,
+        knee: Option<&Knee>,
+        max_size: Option<&MaxSize>,
+    ) -> RunResult {
+        let latency = stat.latency();
+        let rtt = |f: fn(Latency) -> Duration| latency.map(|l| ms(f(l)));
+        RunResult {
+            id,
+            stage: run.stage.clone(),
+            target: run.addr,
+            hertz: run.hertz,
+            byte_size: run.byte_size,
+            count: run.count,
+            duration_secs: run.duration.map(|d| d.as_secs_f64()),
+            deadline_secs: run.timeout.as_secs_f64(),
+            warmup: run.warmup,
+            warmup_secs: run.warmup_time.map(|d| d.as_secs_f64()),
+            cooldown_secs: run.cooldown.as_secs_f64(),
+            open_loop: run.open_loop,
+            ramp: run.ramp.map(|r| r.shape),
+            ramp_to_hz: run.ramp.map(|r| r.to),
+            traffic: run.traffic,
+            seed: run.seed,
+            trace: run.trace.as_ref().map(|t| t.name.clone()),
+            payload: run.payload.clone(),
+            sent: stat.sent,
+            missed: stat.missed,
+            lost: stat.lost(),
+            late: stat.late,
+            reordered: stat.reordered,
+            duplicate: stat.duplicate,
+            unknown: stat.unknown,
+            malformed: stat.malformed,
+            verify: run.verify,
+            truncated: stat.truncated,
+            corrupted: stat.corrupted,
+            loss: stat.loss(),
+            failure: stat.failure(),
+            loss_ci_low: stat.loss_interval().map(|i| i.low),
+            loss_ci_high: stat.loss_interval().map(|i| i.high),
+            ci_width: run.ci_width,
+            max_loss: run.max_loss,
+            passed: run
+                .max_loss
+                .filter(|_| !stat.is_partial())
+                .map(|max_loss| stat.passed(max_loss)),
+            early_stop_margin: run.early_stop.map(|sprt| sprt.margin),
+            decision: stat.decision,
+            stopped_early: stat.decision.is_some(),
+            interrupted: stat.interrupted,
+            skipped: stat.skipped,
+            search: knee.map(|k| k.id),
+            knee_hz: knee.and_then(|k| k.knee_hz),
+            size_search: max_size.map(|m| m.id),
+            max_size: max_size.and_then(|m| m.max_size),
+            rtt_min_ms: rtt(|l| l.min),
+            rtt_mean_ms: rtt(|l| l.mean),
+            rtt_max_ms: rtt(|l| l.max),
+            rtt_stddev_ms: rtt(|l| l.stddev),
+            rtt_p50_ms: rtt(|l| l.p50),
+            rtt_p90_ms: rtt(|l| l.p90),
+            rtt_p99_ms: rtt(|l| l.p99),
+            rtt_p999_ms: rtt(|l| l.p999),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    #[serde(flatten)]
+    pub meta: Meta,
+    pub runs: Vec<RunResult>,
+    /// outcome of the rate searches, one per target and size
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub knees: Vec<Knee>,
+    /// outcome of the size searches, one per target
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub max_sizes: Vec<MaxSize>,
+    /// time buckets of the runs that were rolled up
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<Bucket>,
+    /// commands typed at the console, in the order they were applied
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<Action>,
+    /// loss of the runs by rate and size, one grid per stage and target
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub grids: Vec<Grid>,
+}
+
+/// A run or bucket together with the metadata, a row of the NDJSON and CSV
+/// formats.
+#[derive(Serialize)]
+struct Row<'a, T> {
+    #[serde(flatten)]
+    meta: &'a Meta,
+    #[serde(flatten)]
+    row: &'a T,
+}
+
+impl Report {
+    pub fn write(&self, path: &Path, format: Format) -> Result<(), Error> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut out, self)?;
+                writeln!(out)?;
+            }
+            Format::Ndjson => {
+                let rows = self.rows(&self.runs).chain(self.rows(&self.buckets));
+                for row in rows.chain(self.rows(&self.actions)) {
+                    serde_json::to_writer(&mut out, &row?)?;
+                    writeln!(out)?;
+                }
+            }
+            // a single table, of the buckets if the runs were rolled up
+            Format::Csv if self.buckets.is_empty() => write_csv(&mut out, self.rows(&self.runs))?,
+            Format::Csv => write_csv(&mut out, self.rows(&self.buckets))?,
+            Format::Grid => write_csv(&mut out, grid_rows(&self.grids))?,
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn rows<'a, T: Serialize>(
+        &'a self,
+        rows: &'a [T],
+    ) -> impl Iterator<Item = Result<Value, Error>> + 'a {
+        rows.iter().map(move |row| {
+            to_value(&Row {
+                meta: &self.meta,
+                row,
+            })
+        })
+    }
+}
+
+/// A row per stage, target and rate of the grids, with the failure ratio of
+/// the cells, as shown, in a column per size of any grid.
+fn grid_rows(grids: &[Grid]) -> impl Iterator<Item = Result<Value, Error>> + '_ {
+    let mut sizes: Vec<usize> = grids.iter().flat_map(|g| g.sizes.clone()).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    grids.iter().flat_map(move |grid| {
+        let sizes = sizes.clone();
+        grid.rates_hz
+            .iter()
+            .zip(&grid.cells)
+            .map(move |(rate, cells)| {
+                let mut row = Map::new();
+                row.insert("stage".to_owned(), grid.stage.clone().into());
+                row.insert("target".to_owned(), grid.target.to_string().into());
+                row.insert("rate_hz".to_owned(), to_value(rate)?);
+                for size in &sizes {
+                    let cell = grid
+                        .sizes
+                        .iter()
+                        .position(|s| s == size)
+                        .and_then(|column| cells[column]);
+                    row.insert(size.to_string(), to_value(&cell.map(|c| c.failure))?);
+                }
+                Ok(Value::Object(row))
+            })
+    })
+}
+
+/// Like `serde_json::to_value`, but through the JSON text, so an `f32`
+/// becomes the `f64` it is printed as (`10.1`, not `10.100000381469727`).
+fn to_value(value: &impl Serialize) -> Result<Value, Error> {
+    Ok(serde_json::from_str(&serde_json::to_string(value)?)?)
+}
+
+fn write_csv(
+    out: &mut impl Write,
+    rows: impl Iterator<Item = Result<Value, Error>>,
+) -> Result<(), Error> {
+    for (idx, row) in rows.enumerate() {
+        let row = match row? {
+            Value::Object(row) => row,
+            _ => unreachable!("rows serialize to objects"),
+        };
+        if idx == 0 {
+            let header: Vec<_> = row.keys().map(|k| csv_field(k)).collect();
+            writeln!(out, "{}", header.join(","))?;
+        }
+        let fields: Vec<_> = row.values().map(csv_value).collect();
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => csv_field(s),
+        other => csv_field(&other.to_string()),
+    }
+}
+
+/// Quote a field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn keeps_f32_digits() {
+        assert_eq!(to_value(&10.1f32).unwrap().to_string(), "10.1");
+        assert_eq!(to_value(&Some(0.3f32)).unwrap(), json!(0.3));
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn writes_csv() {
+        let rows = [
+            json!({"stage": "a, b", "rate_hz": 10.1, "passed": null}),
+            json!({"stage": "c", "rate_hz": 20, "passed": true}),
+        ];
+        let mut out = vec![];
+        write_csv(&mut out, rows.into_iter().map(Ok)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "stage,rate_hz,passed\n\"a, b\",10.1,\nc,20,true\n"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    stat::{ms, Stat},
+    track::Event,
+};
+use serde::Serialize;
+use std::{fmt, time::Duration};
+use tokio::time::Instant;
+
+/// Splits a run into fixed time buckets, by when the fate of each packet was
+/// observed, and summarises each bucket once it is over.
+pub struct Rollups {
+    run: usize,
+    start: Instant,
+    len: Duration,
+    /// index of the bucket being filled
+    index: usize,
+    current: Stat,
+}
+
+impl Rollups {
+    pub fn new(run: usize, start: Instant, len: Duration) -> Rollups {
+        Rollups {
+            run,
+            start,
+            len,
+            index: 0,
+            current: Stat::default(),
+        }
+    }
+
+    /// Record an event observed `at`, returning the buckets it closed.
+    pub fn record(&mut self, event: &Event, at: Instant) -> Vec<Bucket> {
+        let index = (at.duration_since(self.start).as_secs_f64() / self.len.as_secs_f64()) as usize;
+        let mut closed = vec![];
+        while self.index < index {
+            closed.push(self.close(self.len));
+        }
+        self.current.record(event);
+        closed
+    }
+
+    /// Close the bucket being filled at the end of the run, `at`.
+    pub fn finish(mut self, at: Instant) -> Bucket {
+        let filled = at
+            .duration_since(self.start)
+            .saturating_sub(self.len * self.index as u32);
+        self.close(filled.min(self.len))
+    }
+
+    fn close(&mut self, secs: Duration) -> Bucket {
+        let stat = std::mem::take(&mut self.current);
+        let latency = stat.latency();
+        let bucket = Bucket {
+            run: self.run,
+            bucket: self.index,
+            start_secs: (self.len * self.index as u32).as_secs_f64(),
+            secs: secs.as_secs_f64(),
+            rate_hz: if secs.is_zero() {
+                0.0
+            } else {
+                stat.sent as f64 / secs.as_secs_f64()
+            },
+            sent: stat.sent,
+            missed: stat.missed,
+            late: stat.late,
+            reordered: stat.reordered,
+            duplicate: stat.duplicate,
+            unknown: stat.unknown,
+            malformed: stat.malformed,
+            truncated: stat.truncated,
+            corrupted: stat.corrupted,
+            loss: stat.loss(),
+            rtt_min_ms: latency.map(|l| ms(l.min)),
+            rtt_mean_ms: latency.map(|l| ms(l.mean)),
+            rtt_max_ms: latency.map(|l| ms(l.max)),
+            rtt_p50_ms: latency.map(|l| ms(l.p50)),
+            rtt_p99_ms: latency.map(|l| ms(l.p99)),
+        };
+        self.index += 1;
+        bucket
+    }
+}
+
+/// Counters and latencies of one time bucket of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    pub run: usize,
+    pub bucket: usize,
+    /// seconds since the start of the run
+    pub start_secs: f64,
+    pub secs: f64,
+    /// packets resolved per second
+    pub rate_hz: f64,
+    pub sent: usize,
+    pub missed: usize,
+    pub late: usize,
+    pub reordered: usize,
+    pub duplicate: usize,
+    pub unknown: usize,
+    pub malformed: usize,
+    pub truncated: usize,
+    pub corrupted: usize,
+    pub loss: f32,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_mean_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    pub rtt_p50_ms: Option<f64>,
+    pub rtt_p99_ms: Option<f64>,
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}s–{:.1}s: {:.1}hz Sent: {} Missed: {} Loss: {:.1}%",
+            self.start_secs,
+            self.start_secs + self.secs,
+            self.rate_hz,
+            self.sent,
+            self.missed,
+            self.loss * 100.0
+        )?;
+        if let (Some(p50), Some(p99)) = (self.rtt_p50_ms, self.rtt_p99_ms) {
+            write!(f, " RTT: p50 {:.2} p99 {:.2} ms", p50, p99)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{Outcome, Reply};
+
+    fn event(seq: usize, outcome: Outcome, at: Instant) -> Event {
+        Event {
+            seq,
+            outcome,
+            sent_at: Some(at - Duration::from_millis(5)),
+            reply: (outcome != Outcome::Missed).then(|| Reply {
+                at,
+                from: "127.0.0.1:2001".parse().unwrap(),
+                damage: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn rolls_up_buckets() {
+        let start = Instant::now();
+        let secs = |s: f64| start + Duration::from_secs_f64(s);
+        let mut rollups = Rollups::new(3, start, Duration::from_secs(10));
+        assert!(rollups
+            .record(&event(0, Outcome::OnTime, secs(0.0)), secs(0.0))
+            .is_empty());
+        assert!(rollups
+            .record(&event(1, Outcome::Missed, secs(9.9)), secs(9.9))
+            .is_empty());
+        // an event at a boundary opens the next bucket
+        let closed = rollups.record(&event(2, Outcome::OnTime, secs(10.0)), secs(10.0));
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].run, closed[0].bucket), (3, 0));
+        assert_eq!((closed[0].sent, closed[0].missed), (2, 1));
+        assert_eq!(closed[0].rate_hz, 0.2);
+        // buckets without events are still closed, empty
+        let closed = rollups.record(&event(3, Outcome::OnTime, secs(35.0)), secs(35.0));
+        let buckets: Vec<_> = closed
+            .iter()
+            .map(|b| (b.bucket, b.start_secs, b.sent))
+            .collect();
+        assert_eq!(buckets, vec![(1, 10.0, 1), (2, 20.0, 0)]);
+        assert_eq!(closed[1].rtt_p50_ms, None);
+        // the last bucket covers only the part of it the run lasted
+        let last = rollups.finish(secs(37.5));
+        assert_eq!((last.bucket, last.start_secs, last.secs), (3, 30.0, 7.5));
+        assert_eq!(last.sent, 1);
+        assert!(last.rtt_p50_ms.is_some());
+    }
+}
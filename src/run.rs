@@ -0,0 +1,809 @@
+use crate::{
+    payload::Payload,
+    profile::{Model, Pace, Pacer, Ramp},
+    protocol::{self, flags, Header},
+    stat::{self, Decision, Sprt},
+    trace::Trace,
+    track::{Damage, Event, Outcome, Reply, Tracker},
+};
+use anyhow::Error;
+use async_stream::try_stream;
+use std::{
+    collections::VecDeque,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_stream::Stream;
+
+/// Identifies this tool instance on the wire.
+#[derive(Debug, Clone, Copy)]
+pub struct Session {
+    pub id: u32,
+    pub epoch: Instant,
+    /// wall clock time at `epoch`
+    pub started: SystemTime,
+    /// frame probes with the 8 byte legacy header
+    pub legacy: bool,
+}
+
+impl Session {
+    pub fn new(legacy: bool) -> Session {
+        Session {
+            id: rand::random(),
+            epoch: Instant::now(),
+            started: SystemTime::now(),
+            legacy,
+        }
+    }
+
+    /// Wall clock time of `at`.
+    pub fn wall_clock(&self, at: Instant) -> SystemTime {
+        self.started + at.duration_since(self.epoch)
+    }
+
+    pub fn header_len(&self) -> usize {
+        if self.legacy {
+            protocol::LEGACY_HEADER_LEN
+        } else {
+            protocol::HEADER_LEN
+        }
+    }
+}
+
+pub struct Run<A: ToSocketAddrs> {
+    pub socket: Arc<UdpSocket>,
+    /// the socket has the don't-fragment option set
+    pub dont_fragment: bool,
+    pub session: Session,
+    /// run id on the wire
+    pub id: u32,
+    pub addr: A,
+    pub hertz: f32,
+    /// how long a reply may take, the send interval unless set
+    pub timeout: Duration,
+    pub byte_size: usize,
+    pub count: Option<usize>,
+    pub duration: Option<Duration>,
+    /// packets sent at the start of the run that are not counted
+    pub warmup: usize,
+    /// minimum time the warm-up lasts, on top of `warmup` packets
+    pub warmup_time: Option<Duration>,
+    /// how long to keep collecting late replies after the last deadline
+    pub cooldown: Duration,
+    pub max_loss: Option<f32>,
+    /// keep sending until the loss confidence interval is at most this wide
+    pub ci_width: Option<f64>,
+    /// stop as soon as the loss is decided relative to a threshold
+    pub early_stop: Option<Sprt>,
+    pub stage: Option<String>,
+    /// send on schedule without waiting for replies
+    pub open_loop: bool,
+    /// move the rate from `hertz` to another over the duration of the run
+    pub ramp: Option<Ramp>,
+    /// length of the time buckets the run is rolled up into
+    pub bucket: Option<Duration>,
+    pub traffic: Model,
+    /// seeds the traffic model together with the run id
+    pub seed: u64,
+    /// recorded timing and sizes to send instead of `hertz` and
+    /// `byte_size`, open-loop only
+    pub trace: Option<Arc<Trace>>,
+    /// check that replies echo the whole probe unchanged
+    pub verify: bool,
+    pub payload: Payload,
+    /// pauses and rate changes from the console
+    pub control: Arc<Control>,
+}
+
+/// Packets per run when neither a count nor a duration is given.
+pub const DEFAULT_COUNT: usize = 100;
+
+/// Shortest time after its deadline that a reply still counts as late.
+const LATE_WINDOW: Duration = Duration::from_secs(10);
+
+impl<A: ToSocketAddrs + Clone + Send + Sync + 'static> Run<A> {
+    pub fn new(
+        socket: Arc<UdpSocket>,
+        session: Session,
+        addr: A,
+        hertz: f32,
+        byte_size: usize,
+    ) -> Run<A> {
+        let timeout = Duration::from_secs_f32(1.0 / hertz);
+        Run {
+            socket,
+            dont_fragment: false,
+            session,
+            id: 0,
+            addr,
+            hertz,
+            timeout,
+            byte_size,
+            count: Some(DEFAULT_COUNT),
+            duration: None,
+            warmup: 0,
+            warmup_time: None,
+            cooldown: Duration::ZERO,
+            max_loss: None,
+            ci_width: None,
+            early_stop: None,
+            stage: None,
+            open_loop: false,
+            ramp: None,
+            bucket: None,
+            traffic: Model::Constant,
+            seed: 0,
+            trace: None,
+            verify: false,
+            payload: Payload::default(),
+            control: Arc::default(),
+        }
+    }
+
+    fn pacer(&self) -> Pacer {
+        let pace = Pace {
+            hertz: self.hertz,
+            ramp: self.ramp,
+            over: self.duration,
+            model: self.traffic,
+            trace: self.trace.clone(),
+        };
+        Pacer::new(pace, self.seed.wrapping_add(self.id as u64))
+    }
+
+    /// Replies are told apart from unknown ones until the deadline plus the
+    /// cool-down, or plus `LATE_WINDOW` if that is longer, after their probe.
+    fn tracker(&self) -> Tracker {
+        Tracker::new(self.timeout + self.cooldown.max(LATE_WINDOW))
+    }
+
+    fn framing(&self) -> Framing {
+        Framing {
+            session: self.session,
+            run: self.id,
+            byte_size: self.byte_size,
+            trace: self.trace.clone(),
+            verify: self.verify,
+            payload: self.payload.clone(),
+            seed: self.seed,
+        }
+    }
+
+    fn warmup(&self) -> Warmup {
+        Warmup {
+            packets: self.warmup,
+            time: self.warmup_time,
+        }
+    }
+
+    pub fn start(&self) -> Pin<Box<dyn Stream<Item = Result<Event, Error>> + '_>> {
+        if self.open_loop {
+            Box::pin(self.open_loop())
+        } else {
+            Box::pin(self.closed_loop())
+        }
+    }
+
+    /// Stop-and-wait: a packet is sent once the previous one has been answered
+    /// or reached its deadline, and the send interval has passed.
+    fn closed_loop(&self) -> impl Stream<Item = Result<Event, Error>> + '_ {
+        let framing = self.framing();
+        try_stream! {
+            let mut tracker = self.tracker();
+            let mut tally = Tally::default();
+            let mut buf = framing.buffer();
+            let warmup = self.warmup();
+            let mut pacer = self.pacer();
+            let start = Instant::now();
+            // first counted sequence number, warm-up packets are never yielded
+            let mut measured = None;
+            let mut measuring = start;
+            let mut end = None;
+            for i in 0.. {
+                if measured.is_none() && warmup.is_over(i, start) {
+                    measured = Some(i);
+                    measuring = Instant::now();
+                    end = self.duration.map(|d| measuring + d);
+                }
+                if measured.is_some_and(|m| finished(self.count, i - m, end))
+                    || tally.settle(self)
+                {
+                    break;
+                }
+                if self.control.is_paused() {
+                    let paused = self.control.wait_paused().await;
+                    measuring += paused;
+                    end = end.map(|e| e + paused);
+                }
+                tracker.sent(i, Instant::now());
+                let probe = framing.encode(i, measured.is_none());
+                send(&self.socket, &probe, self.addr.clone(), self.dont_fragment).await?;
+
+                let gap = pacer.gap(measuring.elapsed()).div_f32(self.control.scale());
+                let interval = time::sleep(gap);
+                let deadline = time::sleep(self.timeout);
+                tokio::pin!(interval, deadline);
+                let mut paced = false;
+                loop {
+                    if deadline.is_elapsed() {
+                        if let Some(event) = tracker.expire(i) {
+                            if counted(&event, measured) {
+                                tally.record(&event);
+                                yield event;
+                            }
+                        }
+                    }
+                    if paced && !tracker.is_outstanding(i) {
+                        break;
+                    }
+                    let recv = tokio::select! {
+                        biased;
+                        () = &mut interval, if !paced => {
+                            paced = true;
+                            continue;
+                        }
+                        () = &mut deadline, if tracker.is_outstanding(i) => continue,
+                        recv = self.socket.recv_from(&mut buf) => recv,
+                    };
+                    let (len, from) = recv?;
+                    let at = Instant::now();
+                    let event = match framing.decode(&buf[..len]) {
+                        Decoded::Reply(seq) => {
+                            let damage = framing.damage(seq, &buf[..len]);
+                            tracker.reply(seq, Reply { at, from, damage })
+                        }
+                        Decoded::Foreign => continue,
+                        Decoded::Malformed => Event::malformed(Reply { at, from, damage: None }),
+                    };
+                    if counted(&event, measured) {
+                        tally.record(&event);
+                        yield event;
+                    }
+                }
+            }
+
+            let drain = time::sleep(self.cooldown);
+            tokio::pin!(drain);
+            loop {
+                let recv = tokio::select! {
+                    biased;
+                    () = &mut drain => break,
+                    recv = self.socket.recv_from(&mut buf) => recv,
+                };
+                let (len, from) = recv?;
+                let at = Instant::now();
+                if let Decoded::Reply(seq) = framing.decode(&buf[..len]) {
+                    let damage = framing.damage(seq, &buf[..len]);
+                    let event = tracker.reply(seq, Reply { at, from, damage });
+                    if counted(&event, measured) {
+                        yield event;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Open-loop: a paced sender task and a receiver task run independently,
+    /// replies are matched to outstanding packets by sequence number.
+    fn open_loop(&self) -> impl Stream<Item = Result<Event, Error>> + '_ {
+        let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+        let (reply_tx, mut reply_rx) = mpsc::unbounded_channel();
+
+        let framing = self.framing();
+        let sender = {
+            let framing = framing.clone();
+            let socket = self.socket.clone();
+            let dont_fragment = self.dont_fragment;
+            let addr = self.addr.clone();
+            let mut pacer = self.pacer();
+            let warmup = self.warmup();
+            let (count, duration) = (self.count, self.duration);
+            let control = self.control.clone();
+            tokio::spawn(async move {
+                let start = Instant::now();
+                let mut next = start;
+                let mut measured = None;
+                let mut measuring = start;
+                let mut end = None;
+                for i in 0.. {
+                    if measured.is_none() && warmup.is_over(i, start) {
+                        measured = Some(i);
+                        measuring = Instant::now();
+                        end = duration.map(|d| measuring + d);
+                    }
+                    if measured.is_some_and(|m| finished(count, i - m, end)) || pacer.is_done() {
+                        break;
+                    }
+                    if control.is_paused() {
+                        let paused = control.wait_paused().await;
+                        next += paused;
+                        measuring += paused;
+                        end = end.map(|e| e + paused);
+                    }
+                    time::sleep_until(next).await;
+                    let now = Instant::now();
+                    let since = now - measuring;
+                    let scale = control.scale();
+                    next += pacer.gap(since).div_f32(scale);
+                    // after a stall, skip the missed sends instead of bursting them out
+                    if next + pacer.interval(since).div_f32(scale) < now {
+                        next = now;
+                    }
+                    let warm = measured.is_none();
+                    // announce the packet before it leaves so its reply can't overtake it
+                    if sent_tx.send(Ok((i, warm, Instant::now()))).is_err() {
+                        break;
+                    }
+                    let probe = framing.encode(i, warm);
+                    if let Err(e) = send(&socket, &probe, addr.clone(), dont_fragment).await {
+                        let _ = sent_tx.send(Err(e));
+                        break;
+                    }
+                }
+            })
+        };
+        let receiver = {
+            let socket = self.socket.clone();
+            tokio::spawn(async move {
+                let mut buf = framing.buffer();
+                loop {
+                    let (len, from) = match socket.recv_from(&mut buf).await {
+                        Ok(recv) => recv,
+                        Err(e) => {
+                            let _ = reply_tx.send(Err(e));
+                            break;
+                        }
+                    };
+                    let at = Instant::now();
+                    // `None` for a malformed reply
+                    let seq = match framing.decode(&buf[..len]) {
+                        Decoded::Reply(seq) => Some(seq),
+                        Decoded::Foreign => continue,
+                        Decoded::Malformed => None,
+                    };
+                    let reply = Reply {
+                        at,
+                        from,
+                        damage: seq.and_then(|seq| framing.damage(seq, &buf[..len])),
+                    };
+                    if reply_tx.send(Ok((seq, reply))).is_err() {
+                        break;
+                    }
+                }
+            })
+        };
+
+        let tasks = (AbortOnDrop(sender), AbortOnDrop(receiver));
+        try_stream! {
+            let _tasks = tasks;
+            let mut tracker = self.tracker();
+            let mut tally = Tally::default();
+            let mut deadlines: VecDeque<(usize, Instant)> = VecDeque::new();
+            let mut measured = None;
+            let mut sending = true;
+            while sending || tracker.has_outstanding() {
+                let next_deadline = deadlines.front().map(|&(_, deadline)| deadline);
+                let expired = async {
+                    match next_deadline {
+                        Some(deadline) => time::sleep_until(deadline).await,
+                        None => futures::future::pending().await,
+                    }
+                };
+                let event = tokio::select! {
+                    biased;
+                    sent = sent_rx.recv(), if sending => match sent {
+                        Some(Ok((i, warm, sent_at))) => {
+                            if !warm && measured.is_none() {
+                                measured = Some(i);
+                            }
+                            tracker.sent(i, sent_at);
+                            deadlines.push_back((i, sent_at + self.timeout));
+                            Ok(None)
+                        }
+                        Some(Err(e)) => Err(e),
+                        None => {
+                            sending = false;
+                            Ok(None)
+                        }
+                    },
+                    () = expired => {
+                        let (num, _) = deadlines.pop_front().unwrap();
+                        Ok(tracker.expire(num))
+                    },
+                    Some(reply) = reply_rx.recv() => reply.map(|(seq, reply)| Some(match seq {
+                        Some(seq) => tracker.reply(seq, reply),
+                        None => Event::malformed(reply),
+                    })),
+                };
+                let event = event?;
+                while let Some(&(num, _)) = deadlines.front() {
+                    if tracker.is_outstanding(num) {
+                        break;
+                    }
+                    deadlines.pop_front();
+                }
+                if let Some(event) = event {
+                    if counted(&event, measured) {
+                        tally.record(&event);
+                        yield event;
+                    }
+                }
+                if sending && tally.settle(self) {
+                    // stops the sender, packets it already announced are still tracked
+                    sent_rx.close();
+                }
+            }
+
+            let drain = time::sleep(self.cooldown);
+            tokio::pin!(drain);
+            loop {
+                let (seq, reply) = tokio::select! {
+                    biased;
+                    () = &mut drain => break,
+                    Some(reply) = reply_rx.recv() => reply,
+                }?;
+                if let Some(seq) = seq {
+                    let event = tracker.reply(seq, reply);
+                    if counted(&event, measured) {
+                        yield event;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Adjustments to a run in progress, and the early-stop decision it ended on.
+pub struct Control {
+    paused: watch::Sender<bool>,
+    /// factor on the send rate, as the bits of an `f32`
+    scale: AtomicU32,
+    decision: OnceLock<Decision>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control {
+            paused: watch::channel(false).0,
+            scale: AtomicU32::new(1.0f32.to_bits()),
+            decision: OnceLock::new(),
+        }
+    }
+}
+
+impl Control {
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.send_replace(paused);
+    }
+
+    pub fn scale(&self) -> f32 {
+        f32::from_bits(self.scale.load(Ordering::Relaxed))
+    }
+
+    pub fn set_scale(&self, scale: f32) {
+        self.scale.store(scale.to_bits(), Ordering::Relaxed);
+    }
+
+    /// The side of `max_loss` the early-stop test stopped the run on, `None`
+    /// if it ran to its end.
+    pub fn decision(&self) -> Option<Decision> {
+        self.decision.get().copied()
+    }
+
+    fn decide(&self, decision: Decision) {
+        let _ = self.decision.set(decision);
+    }
+
+    /// Wait until the run is no longer paused, returning how long that took.
+    async fn wait_paused(&self) -> Duration {
+        let start = Instant::now();
+        let mut paused = self.paused.subscribe();
+        while *paused.borrow() {
+            if paused.changed().await.is_err() {
+                break;
+            }
+        }
+        start.elapsed()
+    }
+}
+
+/// Send a probe. A probe refused locally as larger than the path MTU, with
+/// the don't-fragment option set, is left to expire as missed.
+async fn send<A: ToSocketAddrs>(
+    socket: &UdpSocket,
+    probe: &[u8],
+    addr: A,
+    dont_fragment: bool,
+) -> io::Result<()> {
+    match socket.send_to(probe, addr).await {
+        Err(e) if dont_fragment && e.raw_os_error() == Some(libc::EMSGSIZE) => Ok(()),
+        result => result.map(drop),
+    }
+}
+
+/// Forbid fragmentation of the datagrams sent on `socket`.
+#[cfg(target_os = "linux")]
+pub fn set_dont_fragment(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name, value) = match socket.local_addr()? {
+        SocketAddr::V4(_) => (
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            libc::IP_PMTUDISC_DO,
+        ),
+        SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, 1),
+    };
+    // SAFETY: the option value is a live c_int of the size passed
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_dont_fragment(_socket: &UdpSocket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the don't-fragment option is only supported on Linux",
+    ))
+}
+
+/// Whether a run that has counted `sent` packets and ends at `end` is over.
+fn finished(count: Option<usize>, sent: usize, end: Option<Instant>) -> bool {
+    count.is_some_and(|c| sent >= c) || end.is_some_and(|e| Instant::now() >= e)
+}
+
+/// Whether an event counts towards the run's statistics, given the first
+/// sequence number sent after the warm-up.
+fn counted(event: &Event, measured: Option<usize>) -> bool {
+    matches!(event.outcome, Outcome::Unknown | Outcome::Malformed)
+        || measured.is_some_and(|m| event.seq >= m)
+}
+
+/// Resolved and failed packets of a run so far.
+#[derive(Default)]
+struct Tally {
+    resolved: usize,
+    /// missed, as `Stat::loss_interval` counts them
+    missed: usize,
+    /// missed or answered by a damaged reply, as `Stat::failure` counts them
+    failed: usize,
+    settled: bool,
+}
+
+impl Tally {
+    fn record(&mut self, event: &Event) {
+        match event.outcome {
+            Outcome::OnTime | Outcome::Reordered => {
+                self.resolved += 1;
+                self.failed += event.reply.is_some_and(|r| r.damage.is_some()) as usize;
+            }
+            Outcome::Missed => {
+                self.resolved += 1;
+                self.missed += 1;
+                self.failed += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the run may stop because its loss confidence interval is
+    /// narrow enough or its early-stop test is decided. The decision it
+    /// stops on is kept in the run's `Control`.
+    fn settle<A: ToSocketAddrs>(&mut self, run: &Run<A>) -> bool {
+        if self.settled {
+            return true;
+        }
+        let narrow = run.ci_width.is_some_and(|w| {
+            stat::wilson(self.missed, self.resolved).is_some_and(|i| i.width() <= w)
+        });
+        let decision = run
+            .early_stop
+            .and_then(|sprt| sprt.decide(self.failed, self.resolved));
+        if let Some(decision) = decision {
+            run.control.decide(decision);
+        }
+        self.settled = narrow || decision.is_some();
+        self.settled
+    }
+}
+
+/// How long the uncounted start of a run lasts, both bounds have to be met.
+#[derive(Clone, Copy)]
+struct Warmup {
+    packets: usize,
+    time: Option<Duration>,
+}
+
+impl Warmup {
+    /// Whether the warm-up of a run started at `start` is over once `sent`
+    /// packets have been sent.
+    fn is_over(&self, sent: usize, start: Instant) -> bool {
+        sent >= self.packets && self.time.is_none_or(|t| start.elapsed() >= t)
+    }
+}
+
+/// Aborts a spawned task once the stream owning it is dropped.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// What a received datagram is to a run.
+#[derive(Debug, PartialEq)]
+enum Decoded {
+    /// a reply to the probe with this sequence number
+    Reply(usize),
+    /// a reply to another session or run, such as a straggler of an earlier
+    /// run, which is ignored
+    Foreign,
+    /// too short or garbled to carry a header
+    Malformed,
+}
+
+/// Frames the probes of a run.
+#[derive(Clone)]
+struct Framing {
+    session: Session,
+    run: u32,
+    byte_size: usize,
+    trace: Option<Arc<Trace>>,
+    verify: bool,
+    payload: Payload,
+    /// the run's seed, for random payloads
+    seed: u64,
+}
+
+impl Framing {
+    /// Size of the probe carrying `seq`, `byte_size` or that of packet `seq`
+    /// of the trace, but never less than the header.
+    fn size(&self, seq: usize) -> usize {
+        match &self.trace {
+            Some(trace) => trace.size(seq).max(self.session.header_len()),
+            None => self.byte_size,
+        }
+    }
+
+    /// Write the payload following the header of the probe carrying `seq`.
+    fn fill(&self, seq: usize, payload: &mut [u8]) {
+        let seed = self.seed ^ (self.run as u64) << 40 ^ seq as u64;
+        self.payload.fill(seed, payload);
+    }
+
+    /// A receive buffer, large enough for whole datagrams if replies are
+    /// verified and for a header otherwise.
+    fn buffer(&self) -> Vec<u8> {
+        let len = if self.verify {
+            u16::MAX as usize
+        } else {
+            protocol::HEADER_LEN
+        };
+        vec![0; len]
+    }
+
+    /// Build a probe carrying the sequence number `seq`, flagged as warm-up
+    /// if `warm`.
+    fn encode(&self, seq: usize, warm: bool) -> Vec<u8> {
+        let mut msg = vec![0; self.size(seq)];
+        self.fill(seq, &mut msg[self.session.header_len()..]);
+        if self.session.legacy {
+            protocol::encode_legacy(seq as u64, &mut msg);
+        } else {
+            Header {
+                flags: if warm { flags::WARMUP } else { 0 },
+                session: self.session.id,
+                run: self.run,
+                seq: seq as u64,
+                timestamp: self.session.epoch.elapsed().as_micros() as u64,
+            }
+            .encode(&mut msg);
+        }
+        msg
+    }
+
+    /// How a reply to `seq` differs from its probe, `None` if it is intact
+    /// or replies are not verified.
+    fn damage(&self, seq: usize, reply: &[u8]) -> Option<Damage> {
+        if !self.verify {
+            return None;
+        }
+        let size = self.size(seq);
+        if reply.len() < size {
+            return Some(Damage::Truncated);
+        }
+        let header_len = self.session.header_len();
+        let mut payload = vec![0; size - header_len];
+        self.fill(seq, &mut payload);
+        (reply.len() > size || reply[header_len..] != payload[..]).then_some(Damage::Corrupted)
+    }
+
+    fn decode(&self, buf: &[u8]) -> Decoded {
+        if self.session.legacy {
+            return match protocol::decode_legacy(buf) {
+                Ok(seq) => Decoded::Reply(seq as usize),
+                Err(_) => Decoded::Malformed,
+            };
+        }
+        match Header::decode(buf) {
+            Ok(header) if header.session == self.session.id && header.run == self.run => {
+                Decoded::Reply(header.seq as usize)
+            }
+            Ok(_) => Decoded::Foreign,
+            Err(_) => Decoded::Malformed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framing(payload: &str) -> Framing {
+        Framing {
+            session: Session::new(false),
+            run: 3,
+            byte_size: 48,
+            trace: None,
+            verify: true,
+            payload: payload.parse().unwrap(),
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn finds_damaged_replies() {
+        for payload in ["incrementing", "random"] {
+            let framing = framing(payload);
+            let probe = framing.encode(5, false);
+            assert_eq!(framing.decode(&probe), Decoded::Reply(5));
+            assert_eq!(framing.damage(5, &probe), None);
+            assert_eq!(framing.damage(5, &probe[..40]), Some(Damage::Truncated));
+            let mut longer = probe.clone();
+            longer.push(0);
+            assert_eq!(framing.damage(5, &longer), Some(Damage::Corrupted));
+            let mut flipped = probe.clone();
+            flipped[47] ^= 1;
+            assert_eq!(framing.damage(5, &flipped), Some(Damage::Corrupted));
+            // the payload of another probe
+            assert_eq!(
+                framing.damage(6, &probe),
+                (payload == "random").then_some(Damage::Corrupted)
+            );
+        }
+        let unverified = Framing {
+            verify: false,
+            ..framing("random")
+        };
+        assert_eq!(unverified.damage(5, &[0; 8]), None);
+    }
+}
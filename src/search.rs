@@ -0,0 +1,227 @@
+use serde::Serialize;
+use std::{fmt, net::SocketAddr};
+
+/// Relative width of the bracket at which a search stops.
+const TOLERANCE: f32 = 0.05;
+
+/// Searches the highest rate at which one packet size stays within its loss
+/// threshold: the rate doubles from the lowest until a run fails, then the
+/// bracket between the highest passing and the lowest failing rate is
+/// bisected until it is within `TOLERANCE`.
+pub struct Search {
+    min: f32,
+    max: f32,
+    passed: Option<f32>,
+    failed: Option<f32>,
+}
+
+impl Search {
+    /// A search between the lowest and highest of `rates`.
+    pub fn new(rates: &[f32]) -> Search {
+        Search {
+            min: rates.iter().copied().fold(f32::INFINITY, f32::min),
+            max: rates.iter().copied().fold(0.0, f32::max),
+            passed: None,
+            failed: None,
+        }
+    }
+
+    /// Rate of the next run, `None` once the knee is bracketed.
+    pub fn next(&self) -> Option<f32> {
+        match (self.passed, self.failed) {
+            (None, None) => Some(self.min),
+            // even the lowest rate fails
+            (None, Some(_)) => None,
+            (Some(passed), None) if passed >= self.max => None,
+            (Some(passed), None) => Some((passed * 2.0).min(self.max)),
+            (Some(passed), Some(failed)) if failed <= passed * (1.0 + TOLERANCE) => None,
+            (Some(passed), Some(failed)) => Some((passed + failed) / 2.0),
+        }
+    }
+
+    pub fn record(&mut self, hertz: f32, passed: bool) {
+        if passed {
+            self.passed = Some(self.passed.map_or(hertz, |p| p.max(hertz)));
+        } else {
+            self.failed = Some(self.failed.map_or(hertz, |f| f.min(hertz)));
+        }
+    }
+
+    pub fn knee(&self) -> (Option<f32>, Option<f32>) {
+        (self.passed, self.failed)
+    }
+}
+
+/// Outcome of the search for one target and packet size.
+#[derive(Debug, Clone, Serialize)]
+pub struct Knee {
+    pub id: usize,
+    pub stage: Option<String>,
+    pub target: SocketAddr,
+    pub byte_size: usize,
+    /// highest rate that passed, `None` if even the lowest failed
+    pub knee_hz: Option<f32>,
+    /// lowest rate that failed, `None` if even the highest passed
+    pub fail_hz: Option<f32>,
+    /// ids of the runs of the search
+    pub runs: Vec<usize>,
+}
+
+impl fmt::Display for Knee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Max Rate: {} bytes to {}: ", self.byte_size, self.target)?;
+        match (self.knee_hz, self.fail_hz) {
+            (Some(knee), Some(fail)) => write!(f, "{:.1}hz (fails at {:.1}hz)", knee, fail),
+            (Some(knee), None) => write!(f, "{:.1}hz or more", knee),
+            (None, Some(fail)) => write!(f, "below {:.1}hz", fail),
+            (None, None) => write!(f, "none"),
+        }
+    }
+}
+
+/// Loss increase over the next smaller size at which the loss steps up.
+const STEP: f32 = 0.1;
+
+/// Searches the largest packet size that stays within the loss threshold:
+/// every size of the sweep is run in ascending order, then the gap above the
+/// largest passing size is bisected to the byte.
+pub struct SizeSearch {
+    /// sizes of the sweep still to run, descending
+    sweep: Vec<usize>,
+    /// size, loss and verdict of every run so far
+    results: Vec<(usize, f32, bool)>,
+}
+
+impl SizeSearch {
+    pub fn new(sizes: &[usize]) -> SizeSearch {
+        let mut sweep = sizes.to_vec();
+        sweep.sort_unstable_by(|a, b| b.cmp(a));
+        sweep.dedup();
+        SizeSearch {
+            sweep,
+            results: vec![],
+        }
+    }
+
+    /// Size of the next run, `None` once the largest passing size is found.
+    pub fn next(&self) -> Option<usize> {
+        if let Some(&size) = self.sweep.last() {
+            return Some(size);
+        }
+        match self.bounds() {
+            (Some(passed), Some(failed)) if failed > passed + 1 => Some((passed + failed) / 2),
+            _ => None,
+        }
+    }
+
+    pub fn record(&mut self, byte_size: usize, loss: f32, passed: bool) {
+        self.sweep.retain(|&s| s != byte_size);
+        self.results.push((byte_size, loss, passed));
+    }
+
+    /// Largest passing size and the smallest failing size above it.
+    pub fn bounds(&self) -> (Option<usize>, Option<usize>) {
+        let passed = self.results.iter().filter(|r| r.2).map(|r| r.0).max();
+        let failed = self
+            .results
+            .iter()
+            .filter(|r| !r.2 && passed.is_none_or(|p| r.0 > p))
+            .map(|r| r.0)
+            .min();
+        (passed, failed)
+    }
+
+    /// Sizes whose loss is at least `STEP` above that of the next smaller size.
+    pub fn steps(&self) -> Vec<usize> {
+        let mut results = self.results.clone();
+        results.sort_unstable_by_key(|r| r.0);
+        results
+            .windows(2)
+            .filter(|pair| pair[1].1 - pair[0].1 >= STEP)
+            .map(|pair| pair[1].0)
+            .collect()
+    }
+}
+
+/// Outcome of the size search for one target.
+#[derive(Debug, Clone, Serialize)]
+pub struct MaxSize {
+    pub id: usize,
+    pub stage: Option<String>,
+    pub target: SocketAddr,
+    pub hertz: f32,
+    /// largest size that passed, `None` if even the smallest failed
+    pub max_size: Option<usize>,
+    /// smallest size above it that failed, `None` if even the largest passed
+    pub fail_size: Option<usize>,
+    /// sizes at which the loss steps up
+    pub steps: Vec<usize>,
+    /// ids of the runs of the search
+    pub runs: Vec<usize>,
+}
+
+impl fmt::Display for MaxSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Max Size: {} at {}hz: ", self.target, self.hertz)?;
+        match (self.max_size, self.fail_size) {
+            (Some(max), Some(fail)) => write!(f, "{} bytes (fails at {})", max, fail)?,
+            (Some(max), None) => write!(f, "{} bytes or more", max)?,
+            (None, Some(fail)) => write!(f, "below {} bytes", fail)?,
+            (None, None) => write!(f, "none")?,
+        }
+        if !self.steps.is_empty() {
+            let steps: Vec<_> = self.steps.iter().map(|s| s.to_string()).collect();
+            write!(f, ", loss steps up at {} bytes", steps.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a search against a device that passes up to `limit` hertz.
+    fn search(rates: &[f32], limit: f32) -> (Option<f32>, Option<f32>) {
+        let mut search = Search::new(rates);
+        while let Some(hertz) = search.next() {
+            search.record(hertz, hertz <= limit);
+        }
+        search.knee()
+    }
+
+    #[test]
+    fn brackets_the_knee() {
+        let (knee, fail) = search(&[1.0, 256.0], 40.0);
+        let (knee, fail) = (knee.unwrap(), fail.unwrap());
+        assert!(knee <= 40.0 && fail > 40.0);
+        assert!(fail <= knee * (1.0 + TOLERANCE));
+    }
+
+    #[test]
+    fn bisects_to_the_byte() {
+        let mut search = SizeSearch::new(&[1200, 200, 600, 1000]);
+        let mut sizes = vec![];
+        while let Some(size) = search.next() {
+            sizes.push(size);
+            let loss = if size <= 1232 { 0.0 } else { 1.0 };
+            search.record(size, loss, loss == 0.0);
+        }
+        assert_eq!(&sizes[..4], &[200, 600, 1000, 1200]);
+        assert_eq!(search.bounds(), (Some(1200), None));
+
+        let mut search = SizeSearch::new(&[200, 1000, 1400]);
+        while let Some(size) = search.next() {
+            let loss = if size <= 1232 { 0.0 } else { 1.0 };
+            search.record(size, loss, loss == 0.0);
+        }
+        assert_eq!(search.bounds(), (Some(1232), Some(1233)));
+        assert_eq!(search.steps(), vec![1233]);
+    }
+
+    #[test]
+    fn stops_at_the_bounds() {
+        assert_eq!(search(&[4.0, 64.0], 1000.0), (Some(64.0), None));
+        assert_eq!(search(&[4.0, 64.0], 1.0), (None, Some(4.0)));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::protocol::{self, Header};
+use anyhow::Error;
+use argh::FromArgs;
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use tokio::{net::UdpSocket, time};
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "serve")]
+/// Reflect probes back to their sender
+pub struct ServeArgs {
+    /// address to bind too
+    #[argh(option)]
+    bind: SocketAddr,
+
+    /// seconds between per-peer counter logs
+    #[argh(option, default = "10")]
+    interval: u64,
+
+    /// echo whole datagrams instead of their header, for clients that verify replies
+    #[argh(switch)]
+    full_echo: bool,
+}
+
+#[derive(Default)]
+struct PeerStat {
+    received: usize,
+    echoed: usize,
+    bytes: usize,
+    malformed: usize,
+    /// `received` at the last log, so idle peers aren't logged again.
+    logged: usize,
+}
+
+/// How much of a datagram to echo, `None` if it is too short to answer.
+fn echo_len(buf: &[u8], full_echo: bool) -> Option<usize> {
+    let len = match Header::decode(buf) {
+        Ok(_) => protocol::HEADER_LEN,
+        Err(_) if buf.len() >= protocol::LEGACY_HEADER_LEN => protocol::LEGACY_HEADER_LEN,
+        Err(_) => return None,
+    };
+    Some(if full_echo { buf.len() } else { len })
+}
+
+/// Run the reflector until an unrecoverable socket error.
+///
+/// Probes are answered with their header. Datagrams without a valid header
+/// are treated as legacy probes and answered with their first 8 bytes. With
+/// `--full-echo` both are answered with the whole datagram.
+pub async fn serve(args: ServeArgs) -> Result<(), Error> {
+    let socket = UdpSocket::bind(args.bind).await?;
+    println!("reflecting on {}", socket.local_addr()?);
+
+    let mut peers: HashMap<SocketAddr, PeerStat> = HashMap::new();
+    let mut buf = vec![0; u16::MAX as usize];
+    let mut ticker = time::interval(Duration::from_secs(args.interval.max(1)));
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            recv = socket.recv_from(&mut buf) => {
+                let (len, peer) = recv?;
+                let stat = peers.entry(peer).or_default();
+                stat.received += 1;
+                stat.bytes += len;
+                let echo_len = match echo_len(&buf[..len], args.full_echo) {
+                    Some(echo_len) => echo_len,
+                    None => {
+                        stat.malformed += 1;
+                        continue;
+                    }
+                };
+                match socket.send_to(&buf[..echo_len], peer).await {
+                    Ok(_) => stat.echoed += 1,
+                    Err(e) => eprintln!("{}: {}", peer, e),
+                }
+            }
+            _ = ticker.tick() => {
+                for (peer, stat) in peers.iter_mut().filter(|(_, s)| s.received != s.logged) {
+                    stat.logged = stat.received;
+                    println!(
+                        "{}: received {} ({} bytes), echoed {}, malformed {}",
+                        peer, stat.received, stat.bytes, stat.echoed, stat.malformed
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_headers() {
+        let mut probe = vec![0; 100];
+        Header {
+            flags: 0,
+            session: 1,
+            run: 2,
+            seq: 3,
+            timestamp: 4,
+        }
+        .encode(&mut probe);
+        assert_eq!(echo_len(&probe, false), Some(protocol::HEADER_LEN));
+        assert_eq!(echo_len(&probe, true), Some(100));
+
+        let legacy = [1; 20];
+        assert_eq!(echo_len(&legacy, false), Some(protocol::LEGACY_HEADER_LEN));
+        assert_eq!(echo_len(&legacy, true), Some(20));
+
+        assert_eq!(echo_len(&legacy[..7], false), None);
+        assert_eq!(echo_len(&legacy[..7], true), None);
+    }
+}
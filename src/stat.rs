@@ -0,0 +1,335 @@
+use crate::track::{Damage, Event, Outcome};
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::{fmt, time::Duration};
+
+/// Round-trip times above this many microseconds are clamped.
+const MAX_RTT_MICROS: u64 = 60_000_000;
+
+/// Standard normal quantile of the 95% confidence level of loss intervals.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Counters and round-trip latencies of a single run.
+pub struct Stat {
+    pub sent: usize,
+    /// probes not answered before their deadline
+    pub missed: usize,
+    /// missed probes answered after their deadline
+    pub late: usize,
+    pub reordered: usize,
+    pub duplicate: usize,
+    pub unknown: usize,
+    /// replies without a decodable header
+    pub malformed: usize,
+    /// replies shorter than their probe
+    pub truncated: usize,
+    /// replies whose content differs from their probe
+    pub corrupted: usize,
+    /// probes answered within their window by a damaged reply
+    pub damaged_on_time: usize,
+    pub done: bool,
+    /// the run was cut short by an interrupt
+    pub interrupted: bool,
+    /// the run was ended early from the console
+    pub skipped: bool,
+    /// outcome of the run's early-stop test, if it stopped the run
+    pub decision: Option<Decision>,
+    /// round-trip times of answered probes, in microseconds
+    latency: Histogram<u64>,
+}
+
+impl Default for Stat {
+    fn default() -> Self {
+        Stat {
+            sent: 0,
+            missed: 0,
+            late: 0,
+            reordered: 0,
+            duplicate: 0,
+            unknown: 0,
+            malformed: 0,
+            truncated: 0,
+            corrupted: 0,
+            damaged_on_time: 0,
+            done: false,
+            interrupted: false,
+            skipped: false,
+            decision: None,
+            latency: Histogram::new_with_bounds(1, MAX_RTT_MICROS, 3)
+                .expect("1µs to 60s with 3 significant figures are valid bounds"),
+        }
+    }
+}
+
+impl Stat {
+    pub fn record(&mut self, event: &Event) {
+        match event.outcome {
+            Outcome::OnTime | Outcome::Reordered => {
+                self.sent += 1;
+                self.reordered += (event.outcome == Outcome::Reordered) as usize;
+                self.damaged_on_time += event.reply.is_some_and(|r| r.damage.is_some()) as usize;
+                if let Some(rtt) = event.rtt() {
+                    self.latency.saturating_record(rtt.as_micros() as u64);
+                }
+            }
+            Outcome::Missed => {
+                self.sent += 1;
+                self.missed += 1;
+            }
+            Outcome::Late => self.late += 1,
+            Outcome::Duplicate => self.duplicate += 1,
+            Outcome::Unknown => self.unknown += 1,
+            Outcome::Malformed => self.malformed += 1,
+        }
+        match event.reply.and_then(|reply| reply.damage) {
+            Some(Damage::Truncated) => self.truncated += 1,
+            Some(Damage::Corrupted) => self.corrupted += 1,
+            None => {}
+        }
+    }
+
+    /// Whether any reply could not be matched to a probe within its window.
+    pub fn has_anomalies(&self) -> bool {
+        self.late + self.reordered + self.duplicate + self.unknown + self.malformed + self.damaged()
+            > 0
+    }
+
+    /// Replies that did not echo their probe unchanged.
+    pub fn damaged(&self) -> usize {
+        self.truncated + self.corrupted
+    }
+
+    /// Missed probes that were never answered.
+    pub fn lost(&self) -> usize {
+        self.missed.saturating_sub(self.late)
+    }
+
+    pub fn loss(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.missed as f32 / self.sent as f32
+    }
+
+    /// Ratio of probes that were missed or answered by a damaged reply.
+    pub fn failure(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (self.missed + self.damaged_on_time) as f32 / self.sent as f32
+    }
+
+    /// Whether the run was skipped or interrupted, leaving it without a
+    /// verdict.
+    pub fn is_partial(&self) -> bool {
+        self.skipped || self.interrupted
+    }
+
+    /// Whether the run passes `max_loss`, by the early-stop decision if any.
+    /// Damaged replies count against it like missed probes.
+    pub fn passed(&self, max_loss: f32) -> bool {
+        match self.decision {
+            Some(decision) => decision == Decision::Below,
+            None => self.failure() <= max_loss,
+        }
+    }
+
+    /// 95% confidence interval of the loss ratio, `None` until a probe has
+    /// been sent.
+    pub fn loss_interval(&self) -> Option<Interval> {
+        wilson(self.missed, self.sent)
+    }
+
+    /// Latency summary, `None` until a probe has been answered.
+    pub fn latency(&self) -> Option<Latency> {
+        if self.latency.is_empty() {
+            return None;
+        }
+        let micros = |us: f64| Duration::from_secs_f64(us / 1e6);
+        let at = |q: f64| Duration::from_micros(self.latency.value_at_quantile(q));
+        Some(Latency {
+            min: Duration::from_micros(self.latency.min()),
+            mean: micros(self.latency.mean()),
+            max: Duration::from_micros(self.latency.max()),
+            stddev: micros(self.latency.stdev()),
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            p999: at(0.999),
+        })
+    }
+}
+
+/// A confidence interval of a ratio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Interval {
+    pub fn width(&self) -> f64 {
+        self.high - self.low
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}–{:.1}%", self.low * 100.0, self.high * 100.0)
+    }
+}
+
+/// Wilson score interval of `hits` out of `n` trials at the 95% level.
+pub fn wilson(hits: usize, n: usize) -> Option<Interval> {
+    if n == 0 {
+        return None;
+    }
+    let (n, p, z2) = (n as f64, hits as f64 / n as f64, Z_95 * Z_95);
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let margin = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+    Some(Interval {
+        low: (center - margin).max(0.0),
+        high: (center + margin).min(1.0),
+    })
+}
+
+/// Wald's sequential probability ratio test of the loss ratio against a
+/// threshold, with 5% error rates either way.
+///
+/// Loss ratios within `margin` of the threshold are indifferent, the closer
+/// the actual loss is to the threshold the longer a decision takes.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub threshold: f64,
+    pub margin: f64,
+}
+
+/// Side of the threshold a sequential test decided for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Below,
+    Above,
+}
+
+impl Sprt {
+    const ALPHA: f64 = 0.05;
+    const BETA: f64 = 0.05;
+
+    /// Decision after `missed` of `sent` probes, `None` while undecided.
+    pub fn decide(&self, missed: usize, sent: usize) -> Option<Decision> {
+        let p0 = (self.threshold - self.margin).max(1e-6);
+        let p1 = (self.threshold + self.margin).min(1.0 - 1e-6);
+        let hits = missed as f64;
+        let misses = (sent - missed) as f64;
+        let llr = hits * (p1 / p0).ln() + misses * ((1.0 - p1) / (1.0 - p0)).ln();
+        if llr >= ((1.0 - Self::BETA) / Self::ALPHA).ln() {
+            Some(Decision::Above)
+        } else if llr <= (Self::BETA / (1.0 - Self::ALPHA)).ln() {
+            Some(Decision::Below)
+        } else {
+            None
+        }
+    }
+}
+
+/// Round-trip latency summary of a run.
+#[derive(Debug, Clone, Copy)]
+pub struct Latency {
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub stddev: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+}
+
+pub fn ms(d: Duration) -> f64 {
+    d.as_nanos() as f64 / 1e6
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {:.2} mean {:.2} max {:.2} sd {:.2} | p50 {:.2} p90 {:.2} p99 {:.2} p99.9 {:.2} ms",
+            ms(self.min),
+            ms(self.mean),
+            ms(self.max),
+            ms(self.stddev),
+            ms(self.p50),
+            ms(self.p90),
+            ms(self.p99),
+            ms(self.p999),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::Reply;
+
+    #[test]
+    fn wilson_interval() {
+        assert_eq!(wilson(0, 0), None);
+
+        let none = wilson(0, 100).unwrap();
+        assert!(none.low.abs() < 1e-9);
+        assert!((none.high - 0.0370).abs() < 1e-4);
+
+        let some = wilson(3, 100).unwrap();
+        assert!((some.low - 0.0103).abs() < 1e-4);
+        assert!((some.high - 0.0845).abs() < 1e-4);
+
+        let all = wilson(10, 10).unwrap();
+        assert!((all.high - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sprt_decides_clear_runs() {
+        let sprt = Sprt {
+            threshold: 0.1,
+            margin: 0.05,
+        };
+        assert_eq!(sprt.decide(0, 0), None);
+        assert_eq!(sprt.decide(27, 30), Some(Decision::Above));
+        assert_eq!(sprt.decide(0, 60), Some(Decision::Below));
+        assert_eq!(sprt.decide(3, 30), None);
+    }
+
+    #[test]
+    fn damaged_replies_fail_runs() {
+        let at = tokio::time::Instant::now();
+        let reply = |damage| Event {
+            seq: 0,
+            outcome: Outcome::OnTime,
+            sent_at: Some(at),
+            reply: Some(Reply {
+                at,
+                from: "127.0.0.1:2001".parse().unwrap(),
+                damage,
+            }),
+        };
+        let mut stat = Stat::default();
+        for damage in [None, Some(Damage::Truncated), Some(Damage::Corrupted), None] {
+            stat.record(&reply(damage));
+        }
+        assert_eq!((stat.loss(), stat.failure()), (0.0, 0.5));
+        assert!(stat.passed(0.5));
+        assert!(!stat.passed(0.25));
+    }
+
+    #[test]
+    fn sprt_needs_more_than_one_miss_near_zero() {
+        let sprt = Sprt {
+            threshold: 0.01,
+            margin: 0.005,
+        };
+        assert_eq!(sprt.decide(1, 1), None);
+        assert_eq!(sprt.decide(1, 2), None);
+        assert_eq!(sprt.decide(5, 20), Some(Decision::Above));
+    }
+}
@@ -0,0 +1,302 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Deserialize;
+use std::{convert::TryFrom, fmt::Display, str::FromStr, time::Duration};
+
+/// Relative slack within which a float range still reaches its end despite
+/// rounding.
+const TOLERANCE: f64 = 1e-6;
+
+/// A value that can appear in a sweep list.
+pub trait Value: Copy + PartialOrd + FromStr {
+    const ONE: Self;
+
+    /// Whether the value is finite and above zero, as every value of a list
+    /// must be.
+    fn is_positive(self) -> bool;
+
+    /// Further checks of a value of the list, beyond being positive.
+    fn check(self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// `self + k * step`, `None` on overflow.
+    fn add_times(self, step: Self, k: usize) -> Option<Self>;
+
+    /// `self * factor^k`, `None` on overflow.
+    fn mul_pow(self, factor: Self, k: usize) -> Option<Self>;
+
+    /// Whether the value is at most `end`, allowing for rounding.
+    fn reaches(self, end: Self) -> bool;
+}
+
+/// Rates in hertz.
+impl Value for f32 {
+    const ONE: Self = 1.0;
+
+    fn is_positive(self) -> bool {
+        self.is_finite() && self > 0.0
+    }
+
+    fn check(self) -> Result<(), String> {
+        match Duration::try_from_secs_f32(1.0 / self) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("rate {}hz is too low to send at", self)),
+        }
+    }
+
+    fn add_times(self, step: Self, k: usize) -> Option<Self> {
+        let value = (self as f64 + k as f64 * step as f64) as f32;
+        value.is_finite().then_some(value)
+    }
+
+    fn mul_pow(self, factor: Self, k: usize) -> Option<Self> {
+        let value = (self as f64 * (factor as f64).powi(i32::try_from(k).ok()?)) as f32;
+        value.is_finite().then_some(value)
+    }
+
+    fn reaches(self, end: Self) -> bool {
+        self as f64 <= end as f64 * (1.0 + TOLERANCE)
+    }
+}
+
+impl Value for usize {
+    const ONE: Self = 1;
+
+    fn is_positive(self) -> bool {
+        self > 0
+    }
+
+    fn add_times(self, step: Self, k: usize) -> Option<Self> {
+        step.checked_mul(k)?.checked_add(self)
+    }
+
+    fn mul_pow(self, factor: Self, k: usize) -> Option<Self> {
+        factor
+            .checked_pow(u32::try_from(k).ok()?)?
+            .checked_mul(self)
+    }
+
+    fn reaches(self, end: Self) -> bool {
+        self <= end
+    }
+}
+
+/// A list of values parsed from a comma separated spec.
+///
+/// Every item is either a single value or an inclusive range `start..end`,
+/// optionally followed by an additive (`+step`) or multiplicative (`*factor`)
+/// step. Ranges without a step count up by one. Values must be positive.
+///
+/// `1,2,4..64*2` expands to `1, 2, 4, 8, 16, 32, 64` and `16..200+64` expands
+/// to `16, 80, 144`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Spec<T: Value>(pub Vec<T>)
+where
+    T::Err: Display;
+
+impl<T: Value> FromStr for Spec<T>
+where
+    T::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = vec![];
+        for item in s.split(',').map(str::trim) {
+            if item.is_empty() {
+                return Err(format!("empty item in `{}`", s));
+            }
+            match item.split_once("..") {
+                Some((start, rest)) => expand(item, start, rest, &mut values)?,
+                None => values.push(parse(item)?),
+            }
+        }
+        values.iter().try_for_each(|&v| T::check(v))?;
+        Ok(Spec(values))
+    }
+}
+
+impl<T: Value> TryFrom<String> for Spec<T>
+where
+    T::Err: Display,
+{
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+fn parse<T: Value>(s: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    let value: T = s
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid value `{}`: {}", s, e))?;
+    if !value.is_positive() {
+        return Err(format!("value `{}` must be positive", s));
+    }
+    Ok(value)
+}
+
+fn expand<T: Value>(item: &str, start: &str, rest: &str, values: &mut Vec<T>) -> Result<(), String>
+where
+    T::Err: Display,
+{
+    let start: T = parse(start)?;
+    // the k-th value is computed from the start, so rounding doesn't add up
+    let (end, nth): (T, Box<dyn Fn(usize) -> Option<T>>) =
+        if let Some((end, step)) = rest.split_once('+') {
+            let step: T = parse(step)?;
+            (parse(end)?, Box::new(move |k| start.add_times(step, k)))
+        } else if let Some((end, factor)) = rest.split_once('*') {
+            let factor: T = parse(factor)?;
+            if factor <= T::ONE {
+                return Err(format!("factor in `{}` must be above one", item));
+            }
+            (parse(end)?, Box::new(move |k| start.mul_pow(factor, k)))
+        } else {
+            (parse(rest)?, Box::new(move |k| start.add_times(T::ONE, k)))
+        };
+    if end < start {
+        return Err(format!("range `{}` ends before it starts", item));
+    }
+    let mut last = None;
+    for k in 0.. {
+        let value = match nth(k) {
+            Some(value) if value.reaches(end) => value,
+            // past the largest value, so past the end
+            _ => break,
+        };
+        if last.is_some_and(|last| value <= last) {
+            return Err(format!("step in `{}` is too small", item));
+        }
+        last = Some(value);
+        values.push(if value > end { end } else { value });
+    }
+    Ok(())
+}
+
+/// The order in which the rate × size matrix is run.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Order {
+    /// Every size at the first rate, then every size at the next rate.
+    #[default]
+    RateMajor,
+    /// Every rate at the first size, then every rate at the next size.
+    SizeMajor,
+    /// A random permutation of the matrix.
+    Random,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rate-major" => Ok(Order::RateMajor),
+            "size-major" => Ok(Order::SizeMajor),
+            "random" => Ok(Order::Random),
+            _ => Err(format!(
+                "unknown order `{}`, expected rate-major, size-major or random",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Order {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Expand rates and sizes into the cartesian product of `(hertz, byte_size)`,
+/// `seed` shuffles a random order.
+pub fn matrix(rates: &[f32], sizes: &[usize], order: Order, seed: u64) -> Vec<(f32, usize)> {
+    let mut matrix: Vec<(f32, usize)> = match order {
+        Order::SizeMajor => sizes
+            .iter()
+            .flat_map(|&size| rates.iter().map(move |&rate| (rate, size)))
+            .collect(),
+        Order::RateMajor | Order::Random => rates
+            .iter()
+            .flat_map(|&rate| sizes.iter().map(move |&size| (rate, size)))
+            .collect(),
+    };
+    if order == Order::Random {
+        matrix.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_specs() {
+        assert_eq!(
+            "1,2,4..64*2".parse(),
+            Ok(Spec(vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0]))
+        );
+        assert_eq!("16..200+64".parse(), Ok(Spec(vec![16, 80, 144])));
+        assert_eq!("3..5".parse(), Ok(Spec(vec![3, 4, 5])));
+        let Spec(tenths) = "10..11+0.1".parse::<Spec<f32>>().unwrap();
+        assert_eq!(tenths.len(), 11);
+        assert_eq!((tenths[1], tenths[9], tenths[10]), (10.1, 10.9, 11.0));
+        assert_eq!(
+            format!("{}..{}*2", 1usize << 62, usize::MAX).parse(),
+            Ok(Spec(vec![1usize << 62, 1 << 63]))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        for bad in [
+            "",
+            "1,,2",
+            "0.5,NaN",
+            "inf",
+            "-1",
+            "0",
+            "1e-30",
+            "8..4",
+            "1..8+0",
+            "1..8*1",
+            "1..2+1e-9",
+        ] {
+            assert!(bad.parse::<Spec<f32>>().is_err(), "{}", bad);
+        }
+        for bad in ["0", "0..8", "2..8*x", "1..10+-1"] {
+            assert!(bad.parse::<Spec<usize>>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn orders_matrix() {
+        let rates = [1.0, 2.0];
+        let sizes = [10, 20];
+        assert_eq!(
+            matrix(&rates, &sizes, Order::RateMajor, 0),
+            vec![(1.0, 10), (1.0, 20), (2.0, 10), (2.0, 20)]
+        );
+        assert_eq!(
+            matrix(&rates, &sizes, Order::SizeMajor, 0),
+            vec![(1.0, 10), (2.0, 10), (1.0, 20), (2.0, 20)]
+        );
+        let sizes: Vec<usize> = (1..=20).collect();
+        let random = matrix(&rates, &sizes, Order::Random, 7);
+        assert_eq!(random, matrix(&rates, &sizes, Order::Random, 7));
+        assert_ne!(random, matrix(&rates, &sizes, Order::Random, 8));
+        let mut sorted = random.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(sorted, matrix(&rates, &sizes, Order::RateMajor, 0));
+    }
+}
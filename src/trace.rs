@@ -0,0 +1,343 @@
+use anyhow::{Context, Error};
+use std::{fmt, path::Path, time::Duration};
+
+/// A packet of a trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet {
+    /// time since the first packet of the trace
+    pub offset: Duration,
+    /// UDP payload size
+    pub size: usize,
+}
+
+/// Recorded traffic whose timing and sizes a run replays.
+///
+/// Either a CSV file with one `seconds,bytes` line per packet, relative to
+/// any origin and optionally preceded by a header line, or a pcap capture of
+/// which every UDP datagram is replayed.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub name: String,
+    pub packets: Vec<Packet>,
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> Result<Trace, Error> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("reading trace {}", path.display()))?;
+        let packets = if bytes.starts_with(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+            anyhow::bail!(
+                "{} is a pcapng capture, convert it with `editcap -F pcap`",
+                path.display()
+            )
+        } else if Pcap::magic(&bytes).is_some() {
+            parse_pcap(&bytes)
+        } else {
+            std::str::from_utf8(&bytes)
+                .map_err(Error::from)
+                .and_then(parse_csv)
+        }
+        .with_context(|| format!("parsing trace {}", path.display()))?;
+        if packets.is_empty() {
+            anyhow::bail!("trace {} has no packets", path.display());
+        }
+        Ok(Trace {
+            name: path.display().to_string(),
+            packets,
+        })
+    }
+
+    pub fn span(&self) -> Duration {
+        self.packets.last().map_or(Duration::ZERO, |p| p.offset)
+    }
+
+    /// Average send rate, one packet per second for a trace without a span.
+    pub fn hertz(&self) -> f32 {
+        let span = self.span().as_secs_f32();
+        if span > 0.0 {
+            (self.packets.len() - 1) as f32 / span
+        } else {
+            1.0
+        }
+    }
+
+    /// Time between packet `seq` and the one before it, zero past the end.
+    pub fn gap(&self, seq: usize) -> Duration {
+        match (self.packets.get(seq), seq.checked_sub(1)) {
+            (Some(packet), Some(prev)) => packet.offset - self.packets[prev].offset,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Size of packet `seq`, that of the last packet past the end.
+    pub fn size(&self, seq: usize) -> usize {
+        self.packets
+            .get(seq)
+            .or(self.packets.last())
+            .map_or(0, |p| p.size)
+    }
+
+    pub fn min_size(&self) -> usize {
+        self.packets.iter().map(|p| p.size).min().unwrap_or(0)
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.packets.iter().map(|p| p.size).max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} packets over {:.1}s, {}–{} bytes)",
+            self.name,
+            self.packets.len(),
+            self.span().as_secs_f64(),
+            self.min_size(),
+            self.max_size()
+        )
+    }
+}
+
+/// Sort packets by time and make their offsets relative to the first.
+fn relative(mut packets: Vec<(Duration, usize)>) -> Vec<Packet> {
+    packets.sort_by_key(|p| p.0);
+    let origin = packets.first().map_or(Duration::ZERO, |p| p.0);
+    packets
+        .into_iter()
+        .map(|(at, size)| Packet {
+            offset: at - origin,
+            size,
+        })
+        .collect()
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line
+            .split_once(',')
+            .map(|(secs, size)| (secs.trim().parse::<f64>(), size.trim().parse::<usize>()));
+        match fields {
+            Some((Ok(secs), Ok(size))) if secs.is_finite() && secs >= 0.0 => {
+                packets.push((Duration::from_secs_f64(secs), size))
+            }
+            // a header line
+            _ if packets.is_empty() && idx == 0 => {}
+            _ => anyhow::bail!("line {}: expected `seconds,bytes`, got `{}`", idx + 1, line),
+        }
+    }
+    Ok(relative(packets))
+}
+
+/// Link-layer header types of the captures that can be replayed.
+mod link {
+    pub const NULL: u32 = 0;
+    pub const ETHERNET: u32 = 1;
+    pub const RAW: u32 = 101;
+    pub const LINUX_SLL: u32 = 113;
+    pub const IPV4: u32 = 228;
+    pub const IPV6: u32 = 229;
+    pub const LINUX_SLL2: u32 = 276;
+}
+
+const UDP: u8 = 17;
+
+/// Byte order and timestamp resolution of a classic pcap file.
+#[derive(Clone, Copy)]
+struct Pcap {
+    big_endian: bool,
+    nanos: bool,
+}
+
+impl Pcap {
+    fn magic(bytes: &[u8]) -> Option<Pcap> {
+        let (big_endian, nanos) = match bytes.get(..4)? {
+            [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+            _ => return None,
+        };
+        Some(Pcap { big_endian, nanos })
+    }
+
+    fn u32(&self, bytes: &[u8], at: usize) -> Option<u32> {
+        let bytes = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn parse_pcap(bytes: &[u8]) -> Result<Vec<Packet>, Error> {
+    let pcap = Pcap::magic(bytes).context("not a pcap capture")?;
+    let link = pcap.u32(bytes, 20).context("truncated pcap header")?;
+    let mut packets = vec![];
+    let mut at = 24;
+    while at < bytes.len() {
+        let (secs, frac, len) = match (
+            pcap.u32(bytes, at),
+            pcap.u32(bytes, at + 4),
+            pcap.u32(bytes, at + 8),
+        ) {
+            (Some(secs), Some(frac), Some(len)) => (secs, frac, len as usize),
+            _ => anyhow::bail!("truncated record header at byte {}", at),
+        };
+        let data = bytes
+            .get(at + 16..at + 16 + len)
+            .with_context(|| format!("truncated record at byte {}", at))?;
+        at += 16 + len;
+        let nanos = if pcap.nanos {
+            frac as u64
+        } else {
+            frac as u64 * 1000
+        };
+        if let Some(size) = udp_payload(link, data)? {
+            let at = Duration::from_secs(secs as u64) + Duration::from_nanos(nanos);
+            packets.push((at, size));
+        }
+    }
+    Ok(relative(packets))
+}
+
+/// UDP payload size of a captured frame, `None` if it is not a UDP datagram
+/// or a fragment of one after the first.
+fn udp_payload(link: u32, frame: &[u8]) -> Result<Option<usize>, Error> {
+    let be16 = |at: usize| {
+        frame
+            .get(at..at + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let ip = match link {
+        link::NULL => 4,
+        link::RAW | link::IPV4 | link::IPV6 => 0,
+        link::LINUX_SLL => 16,
+        link::LINUX_SLL2 => 20,
+        link::ETHERNET => {
+            let mut at = 12;
+            // skip VLAN tags
+            while matches!(be16(at), Some(0x8100 | 0x88a8)) {
+                at += 4;
+            }
+            match be16(at) {
+                Some(0x0800 | 0x86dd) => at + 2,
+                _ => return Ok(None),
+            }
+        }
+        _ => anyhow::bail!("unsupported link type {}", link),
+    };
+    let ip = match frame.get(ip..) {
+        Some(ip) if !ip.is_empty() => ip,
+        _ => return Ok(None),
+    };
+    let udp = match ip[0] >> 4 {
+        4 => ipv4_udp(ip),
+        6 => ipv6_udp(ip),
+        _ => None,
+    };
+    Ok(udp
+        .and_then(|at| ip.get(at + 4..at + 6))
+        .and_then(|len| (u16::from_be_bytes([len[0], len[1]]) as usize).checked_sub(8)))
+}
+
+/// Offset of the UDP header in an IPv4 packet.
+fn ipv4_udp(ip: &[u8]) -> Option<usize> {
+    let fragment = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x1fff;
+    (*ip.get(9)? == UDP && fragment == 0).then_some((ip[0] & 0x0f) as usize * 4)
+}
+
+/// Offset of the UDP header in an IPv6 packet, past its extension headers.
+fn ipv6_udp(ip: &[u8]) -> Option<usize> {
+    let mut next = *ip.get(6)?;
+    let mut at = 40;
+    loop {
+        match next {
+            UDP => return Some(at),
+            // hop-by-hop, routing and destination options
+            0 | 43 | 60 => {
+                next = *ip.get(at)?;
+                at += (*ip.get(at + 1)? as usize + 1) * 8;
+            }
+            // fragment
+            44 => {
+                if u16::from_be_bytes([*ip.get(at + 2)?, *ip.get(at + 3)?]) >> 3 != 0 {
+                    return None;
+                }
+                next = *ip.get(at)?;
+                at += 8;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_csv() {
+        let packets = parse_csv("time,size\n10.5,100\n\n# idle\n10.75,1200\n10.5,64\n").unwrap();
+        let offsets: Vec<_> = packets.iter().map(|p| p.offset.as_secs_f64()).collect();
+        let sizes: Vec<_> = packets.iter().map(|p| p.size).collect();
+        assert_eq!(offsets, vec![0.0, 0.0, 0.25]);
+        assert_eq!(sizes, vec![100, 64, 1200]);
+        assert!(parse_csv("0,100\nfoo,12\n").is_err());
+    }
+
+    /// An Ethernet frame carrying an IPv4 datagram with `payload` bytes.
+    fn frame(protocol: u8, payload: usize) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend([0x08, 0x00]);
+        let total = (20 + 8 + payload) as u16;
+        frame.extend([0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0]);
+        frame[16..18].copy_from_slice(&total.to_be_bytes());
+        frame.extend([0; 8]);
+        frame.extend([0; 8]);
+        frame[38..40].copy_from_slice(&((8 + payload) as u16).to_be_bytes());
+        // captured with a snap length of 64
+        frame.resize((14 + 20 + 8 + payload).min(64), 0);
+        frame
+    }
+
+    #[test]
+    fn parses_pcap() {
+        let mut pcap = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        pcap.extend([0; 8]);
+        pcap.extend(64u32.to_le_bytes());
+        pcap.extend(link::ETHERNET.to_le_bytes());
+        for (secs, micros, protocol, payload) in [
+            (100, 500_000, UDP, 1200),
+            (100, 750_000, 6, 40),
+            (101, 0, UDP, 16),
+        ] {
+            let frame = frame(protocol, payload);
+            for field in [secs, micros, frame.len() as u32, frame.len() as u32] {
+                pcap.extend(field.to_le_bytes());
+            }
+            pcap.extend(frame);
+        }
+        let packets = parse_pcap(&pcap).unwrap();
+        assert_eq!(
+            packets,
+            vec![
+                Packet {
+                    offset: Duration::ZERO,
+                    size: 1200
+                },
+                Packet {
+                    offset: Duration::from_millis(500),
+                    size: 16
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// What happened to a probe, or to a reply that could not be matched to one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// answered within its window
+    OnTime,
+    /// answered within its window, after a later probe was already answered
+    Reordered,
+    /// the window expired without a reply
+    Missed,
+    /// answered after its window expired
+    Late,
+    /// a second reply to an answered probe
+    Duplicate,
+    /// a reply to a sequence number this run never sent
+    Unknown,
+    /// a reply too short or garbled to carry a probe header, as from a
+    /// reflector that only echoes legacy probes
+    Malformed,
+}
+
+/// How an echoed datagram differs from its probe.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Damage {
+    /// shorter than the probe
+    Truncated,
+    /// as long as the probe or longer, with different content
+    Corrupted,
+}
+
+/// A reply as it arrived on the socket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reply {
+    pub at: Instant,
+    pub from: SocketAddr,
+    /// `None` unless the run verifies its replies
+    pub damage: Option<Damage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub seq: usize,
+    pub outcome: Outcome,
+    /// when the probe was sent, `None` for unknown and malformed replies
+    pub sent_at: Option<Instant>,
+    /// the reply, `None` for missed probes
+    pub reply: Option<Reply>,
+}
+
+impl Event {
+    /// A reply without a sequence number, `seq` is meaningless.
+    pub fn malformed(reply: Reply) -> Event {
+        Event {
+            seq: 0,
+            outcome: Outcome::Malformed,
+            sent_at: None,
+            reply: Some(reply),
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        Some(self.reply?.at - self.sent_at?)
+    }
+}
+
+/// Matches the replies of a run to its probes by sequence number.
+///
+/// Probes are forgotten once they were sent `retain` ago and are no longer
+/// outstanding, replies to them count as unknown. This bounds the memory of
+/// long runs.
+pub struct Tracker {
+    retain: Duration,
+    /// sequence numbers and send times of the remembered probes, oldest first
+    sent: VecDeque<(usize, Instant)>,
+    /// send time of probes still within their window
+    outstanding: HashMap<usize, Instant>,
+    /// send time of probes whose window expired without a reply
+    expired: HashMap<usize, Instant>,
+    /// send time of answered probes
+    answered: HashMap<usize, Instant>,
+    /// highest sequence number answered within its window
+    highest: Option<usize>,
+}
+
+impl Tracker {
+    pub fn new(retain: Duration) -> Tracker {
+        Tracker {
+            retain,
+            sent: VecDeque::new(),
+            outstanding: HashMap::new(),
+            expired: HashMap::new(),
+            answered: HashMap::new(),
+            highest: None,
+        }
+    }
+
+    pub fn sent(&mut self, seq: usize, at: Instant) {
+        self.forget(at);
+        self.outstanding.insert(seq, at);
+        self.sent.push_back((seq, at));
+    }
+
+    /// Forget the probes sent `retain` before `now` whose window is closed.
+    fn forget(&mut self, now: Instant) {
+        while let Some(&(seq, at)) = self.sent.front() {
+            if now.saturating_duration_since(at) < self.retain || self.is_outstanding(seq) {
+                break;
+            }
+            self.sent.pop_front();
+            self.expired.remove(&seq);
+            self.answered.remove(&seq);
+        }
+    }
+
+    pub fn is_outstanding(&self, seq: usize) -> bool {
+        self.outstanding.contains_key(&seq)
+    }
+
+    pub fn has_outstanding(&self) -> bool {
+        !self.outstanding.is_empty()
+    }
+
+    /// Close the window of `seq`, `Missed` if it is still unanswered.
+    pub fn expire(&mut self, seq: usize) -> Option<Event> {
+        let sent_at = self.outstanding.remove(&seq)?;
+        self.expired.insert(seq, sent_at);
+        Some(Event {
+            seq,
+            outcome: Outcome::Missed,
+            sent_at: Some(sent_at),
+            reply: None,
+        })
+    }
+
+    /// Classify a reply carrying `seq`.
+    pub fn reply(&mut self, seq: usize, reply: Reply) -> Event {
+        let (outcome, sent_at) = if let Some(sent_at) = self.outstanding.remove(&seq) {
+            self.answered.insert(seq, sent_at);
+            let reordered = self.highest.is_some_and(|h| h > seq);
+            self.highest = self.highest.max(Some(seq));
+            let outcome = if reordered {
+                Outcome::Reordered
+            } else {
+                Outcome::OnTime
+            };
+            (outcome, Some(sent_at))
+        } else if let Some(sent_at) = self.expired.remove(&seq) {
+            self.answered.insert(seq, sent_at);
+            (Outcome::Late, Some(sent_at))
+        } else if let Some(&sent_at) = self.answered.get(&seq) {
+            (Outcome::Duplicate, Some(sent_at))
+        } else {
+            (Outcome::Unknown, None)
+        };
+        Event {
+            seq,
+            outcome,
+            sent_at,
+            reply: Some(reply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(tracker: &mut Tracker, seq: usize) -> Outcome {
+        let reply = Reply {
+            at: Instant::now(),
+            from: "127.0.0.1:2001".parse().unwrap(),
+            damage: None,
+        };
+        tracker.reply(seq, reply).outcome
+    }
+
+    #[test]
+    fn classifies_replies() {
+        let mut tracker = Tracker::new(Duration::from_secs(60));
+        let start = Instant::now();
+        for seq in 0..4 {
+            tracker.sent(seq, start);
+        }
+        assert_eq!(outcome(&mut tracker, 0), Outcome::OnTime);
+        assert_eq!(outcome(&mut tracker, 2), Outcome::OnTime);
+        assert_eq!(outcome(&mut tracker, 1), Outcome::Reordered);
+        assert_eq!(outcome(&mut tracker, 1), Outcome::Duplicate);
+        assert_eq!(tracker.expire(3).map(|e| e.outcome), Some(Outcome::Missed));
+        assert_eq!(tracker.expire(3), None);
+        assert!(!tracker.has_outstanding());
+        assert_eq!(outcome(&mut tracker, 3), Outcome::Late);
+        assert_eq!(outcome(&mut tracker, 3), Outcome::Duplicate);
+        assert_eq!(outcome(&mut tracker, 7), Outcome::Unknown);
+    }
+
+    #[test]
+    fn forgets_old_probes() {
+        let mut tracker = Tracker::new(Duration::from_secs(1));
+        let start = Instant::now();
+        tracker.sent(0, start);
+        tracker.sent(1, start);
+        tracker.expire(1);
+        assert_eq!(outcome(&mut tracker, 0), Outcome::OnTime);
+        tracker.sent(2, start + Duration::from_secs(2));
+        assert_eq!(tracker.sent.len(), 1);
+        assert_eq!(outcome(&mut tracker, 0), Outcome::Unknown);
+        assert_eq!(outcome(&mut tracker, 1), Outcome::Unknown);
+        // still outstanding, so remembered however old
+        tracker.sent(3, start + Duration::from_secs(4));
+        assert_eq!(outcome(&mut tracker, 2), Outcome::OnTime);
+    }
+}
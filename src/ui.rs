@@ -0,0 +1,422 @@
+use crate::{
+    grid::{self, Cell, Grid, Point},
+    keys::{Key, Keys},
+    payload::Payload,
+    profile::{Model, Ramp},
+    rollup::Bucket,
+    search::{Knee, MaxSize},
+    stat::Stat,
+    trace::Trace,
+};
+use anyhow::Error;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use superconsole::{
+    style::{StyledContent, Stylize},
+    Component, Dimensions, DrawMode, Line, Span, State, SuperConsole,
+};
+
+#[derive(Debug, Clone)]
+pub struct RunComponent {
+    pub id: usize,
+    pub hertz: f32,
+    pub byte_size: usize,
+    pub stage: Option<String>,
+    pub target: SocketAddr,
+    pub max_loss: Option<f32>,
+    pub ramp: Option<Ramp>,
+    pub traffic: Model,
+    pub trace: Option<Arc<Trace>>,
+    pub payload: Payload,
+    /// whether probes use the legacy format
+    pub legacy: bool,
+    /// factor on the rate from `+` and `-`
+    pub scale: f32,
+    pub paused: bool,
+}
+
+impl Component for RunComponent {
+    fn draw_unchecked(
+        &self,
+        state: &State,
+        _dimensions: Dimensions,
+        _mode: DrawMode,
+    ) -> Result<Vec<Line>, Error> {
+        let stat = state.get::<HashMap<usize, Stat>>()?.get(&self.id);
+        let mut messages = vec![];
+        let mut rate = match self.ramp {
+            Some(ramp) => format!("{}hz to {}hz ({})", self.hertz, ramp.to, ramp.shape),
+            None => format!("{}hz", self.hertz),
+        };
+        if self.traffic != Model::Constant {
+            rate += &format!(", {}", self.traffic);
+        }
+        if self.scale != 1.0 {
+            rate += &format!(", nudged ×{:.2}", self.scale);
+        }
+        match &self.trace {
+            Some(trace) => {
+                messages.push(vec![format!("{}. Replay: {}", self.id, trace)].try_into()?)
+            }
+            None => messages.push(vec![format!("{}. Rate: {}", self.id, rate)].try_into()?),
+        }
+        if let Some(stage) = &self.stage {
+            messages.push(vec![format!("   Stage: {} ({})", stage, self.target)].try_into()?);
+        }
+        let payload = if self.payload == Payload::default() {
+            String::new()
+        } else {
+            format!(", {}", self.payload)
+        };
+        if self.trace.is_none() {
+            messages.push(
+                vec![format!(
+                    "   Packet Size: {} bytes{}",
+                    self.byte_size, payload
+                )]
+                .try_into()?,
+            );
+        } else if !payload.is_empty() {
+            messages.push(vec![format!("   Payload: {}", self.payload)].try_into()?);
+        }
+        match stat {
+            Some(stat) => {
+                let sent = Span::new_styled(format!("   Sent: {} ", stat.sent).to_owned().blue())?;
+                let missed =
+                    Span::new_styled(format!("Missed: {} ", stat.missed).to_owned().yellow())?;
+                let mut line = superconsole::line!(sent, missed);
+                if let Some(interval) = stat.loss_interval() {
+                    line.0.push(Span::new_unstyled(format!(
+                        "Loss: {:.1}% (95% CI {}) ",
+                        stat.loss() * 100.0,
+                        interval
+                    ))?);
+                }
+                if stat.damaged_on_time > 0 {
+                    line.0.push(Span::new_unstyled(format!(
+                        "Damaged: {:.1}% ",
+                        (stat.failure() - stat.loss()) * 100.0
+                    ))?);
+                }
+                if self.paused && !stat.done {
+                    line.0.push(Span::new_styled("PAUSED".to_owned().yellow())?);
+                } else if stat.interrupted {
+                    line.0
+                        .push(Span::new_styled("INTERRUPTED".to_owned().yellow())?);
+                } else if stat.skipped {
+                    line.0
+                        .push(Span::new_styled("SKIPPED".to_owned().yellow())?);
+                } else if let (Some(max_loss), true) = (self.max_loss, stat.done) {
+                    let early = if stat.decision.is_some() {
+                        " (decided early)"
+                    } else {
+                        ""
+                    };
+                    let verdict = if stat.passed(max_loss) {
+                        format!("PASS{}", early).green()
+                    } else {
+                        format!("FAIL{}", early).red()
+                    };
+                    line.0.push(Span::new_styled(verdict)?);
+                }
+                messages.push(line);
+                if stat.has_anomalies() {
+                    let mut anomalies = format!(
+                        "   Lost: {} Late: {} Reordered: {} Duplicate: {} Unknown: {}",
+                        stat.lost(),
+                        stat.late,
+                        stat.reordered,
+                        stat.duplicate,
+                        stat.unknown
+                    );
+                    if stat.malformed > 0 {
+                        anomalies += &format!(" Malformed: {}", stat.malformed);
+                    }
+                    if stat.damaged() > 0 {
+                        anomalies += &format!(
+                            " Truncated: {} Corrupted: {}",
+                            stat.truncated, stat.corrupted
+                        );
+                    }
+                    messages.push(vec![anomalies].try_into()?);
+                }
+                if stat.malformed > 0 && !self.legacy {
+                    let hint = "   Replies lack the probe header, reflectors that echo 8 bytes \
+                                need --legacy";
+                    messages.push(superconsole::line!(Span::new_styled(
+                        hint.to_owned().yellow()
+                    )?));
+                }
+                if let Some(latency) = stat.latency() {
+                    messages.push(vec![format!("   RTT: {}", latency)].try_into()?);
+                }
+                let buckets = state.get::<Vec<Bucket>>()?;
+                if let Some(bucket) = buckets.iter().rev().find(|b| b.run == self.id) {
+                    messages.push(vec![format!("   Last {}", bucket)].try_into()?);
+                }
+            }
+            None => {
+                let not = Span::new_styled("   Not Started".to_owned().red().bold())?;
+                messages.push(superconsole::line!(not));
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// The runs started so far, followed by the outcome of finished searches
+/// and, if keys are read, what they do. The final frame ends with the loss
+/// grids.
+#[derive(Debug)]
+struct Runs {
+    keys: bool,
+    /// show the median RTT in the grid cells
+    grid_rtt: bool,
+}
+
+impl Component for Runs {
+    fn draw_unchecked(
+        &self,
+        state: &State,
+        dimensions: Dimensions,
+        mode: DrawMode,
+    ) -> Result<Vec<Line>, Error> {
+        let mut lines = vec![];
+        for component in state.get::<Vec<RunComponent>>()? {
+            lines.extend(component.draw_unchecked(state, dimensions, mode)?);
+        }
+        for knee in state.get::<Vec<Knee>>()? {
+            lines.push(vec![knee.to_string()].try_into()?);
+        }
+        for max_size in state.get::<Vec<MaxSize>>()? {
+            lines.push(vec![max_size.to_string()].try_into()?);
+        }
+        if self.keys && mode == DrawMode::Normal {
+            lines.push(vec!["p: pause  s: skip  q: quit  +/-: rate".to_owned()].try_into()?);
+        }
+        if mode == DrawMode::Final {
+            for grid in grids(state)? {
+                lines.extend(grid_lines(&grid, self.grid_rtt)?);
+            }
+        }
+        Ok(lines)
+    }
+}
+
+/// Loss grids of the runs at a fixed rate and size.
+fn grids(state: &State) -> Result<Vec<Grid>, Error> {
+    let stats = state.get::<HashMap<usize, Stat>>()?;
+    let points = state
+        .get::<Vec<RunComponent>>()?
+        .iter()
+        .filter(|c| c.ramp.is_none() && c.trace.is_none())
+        .filter_map(|c| {
+            Some(Point {
+                run: c.id,
+                stage: c.stage.as_deref(),
+                target: c.target,
+                hertz: c.hertz,
+                byte_size: c.byte_size,
+                max_loss: c.max_loss,
+                stat: stats.get(&c.id)?,
+            })
+        });
+    Ok(grid::grids(points))
+}
+
+/// A grid with a row per rate and a column per size, each cell coloured by
+/// its loss.
+fn grid_lines(grid: &Grid, rtt: bool) -> Result<Vec<Line>, Error> {
+    const RATE_WIDTH: usize = 10;
+    let width = if rtt { 16 } else { 8 };
+    let title = match &grid.stage {
+        Some(stage) => format!("Loss: {} ({})", stage, grid.target),
+        None => format!("Loss: {}", grid.target),
+    };
+    let mut header = " ".repeat(RATE_WIDTH);
+    for size in &grid.sizes {
+        header += &format!("{:>width$}", format!("{}B", size), width = width);
+    }
+    let mut lines = vec![vec![title].try_into()?, vec![header].try_into()?];
+    let marked = grid
+        .cells
+        .iter()
+        .flatten()
+        .flatten()
+        .any(|c| c.passed.is_some());
+    for (rate, row) in grid.rates_hz.iter().zip(&grid.cells) {
+        let rate = format!("{:>width$}", format!("{}hz", rate), width = RATE_WIDTH);
+        let mut line = superconsole::line!(Span::new_unstyled(rate)?);
+        for cell in row {
+            let span = match cell {
+                Some(cell) => {
+                    let mark = match cell.passed {
+                        Some(true) => "✓",
+                        Some(false) => "✗",
+                        None if marked => " ",
+                        None => "",
+                    };
+                    let mut text = format!("{:.1}%{}", cell.failure * 100.0, mark);
+                    if let (true, Some(p50)) = (rtt, cell.rtt_p50_ms) {
+                        text += &format!(" {:.2}ms", p50);
+                    }
+                    Span::new_styled(shade(cell, format!("{:>width$}", text, width = width)))?
+                }
+                None => Span::new_unstyled(format!("{:>width$}", "-", width = width))?,
+            };
+            line.0.push(span);
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+/// Colour a cell by its loss, counting damaged replies: green for none,
+/// yellow up to 10% and red above.
+fn shade(cell: &Cell, text: String) -> StyledContent<String> {
+    if cell.failure == 0.0 {
+        text.green()
+    } else if cell.failure <= 0.1 {
+        text.yellow()
+    } else {
+        text.red()
+    }
+}
+
+/// Where the progress of the runs is shown.
+pub enum Ui {
+    /// the console, redrawn in place after every packet, and the keys typed
+    /// at it
+    Console(SuperConsole, Option<Keys>),
+    /// plain text, one block per completed run
+    Headless {
+        last_progress: Instant,
+        grid_rtt: bool,
+    },
+}
+
+/// How often headless mode reports on the run in progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Ui {
+    /// The console, unless `headless` is set or stdout is not a TTY.
+    /// `grid_rtt` adds the median RTT to the final loss grids.
+    pub fn new(headless: bool, grid_rtt: bool) -> Ui {
+        if !headless {
+            let keys = Keys::listen();
+            let runs = Runs {
+                keys: keys.is_some(),
+                grid_rtt,
+            };
+            if let Some(console) = SuperConsole::new(Box::new(runs)) {
+                return Ui::Console(console, keys);
+            }
+        }
+        Ui::Headless {
+            last_progress: Instant::now(),
+            grid_rtt,
+        }
+    }
+
+    pub fn render(&mut self, state: &State) -> Result<(), Error> {
+        match self {
+            Ui::Console(console, _) => console.render(state),
+            Ui::Headless { last_progress, .. } => {
+                if last_progress.elapsed() < PROGRESS_INTERVAL {
+                    return Ok(());
+                }
+                *last_progress = Instant::now();
+                let stats = state.get::<HashMap<usize, Stat>>()?;
+                if let Some((id, stat)) = stats.iter().find(|(_, stat)| !stat.done) {
+                    println!(
+                        "{}. Sent: {} Missed: {} (running)",
+                        id, stat.sent, stat.missed
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Show the result of run `id` once it has finished.
+    pub fn finish_run(&mut self, id: usize, state: &State) -> Result<(), Error> {
+        match self {
+            Ui::Console(console, _) => console.render(state),
+            Ui::Headless { last_progress, .. } => {
+                *last_progress = Instant::now();
+                let component = &state.get::<Vec<RunComponent>>()?[id];
+                let lines =
+                    component.draw_unchecked(state, Dimensions::default(), DrawMode::Final)?;
+                for line in lines {
+                    println!("{}", plain(&line));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Show a time bucket of a run once it is over.
+    pub fn finish_bucket(&mut self, bucket: &Bucket) -> Result<(), Error> {
+        if let Ui::Headless { last_progress, .. } = self {
+            *last_progress = Instant::now();
+            println!("{}. {}", bucket.run, bucket);
+        }
+        Ok(())
+    }
+
+    /// Show the outcome of a search once it has finished.
+    pub fn finish_search(
+        &mut self,
+        outcome: &dyn fmt::Display,
+        state: &State,
+    ) -> Result<(), Error> {
+        match self {
+            Ui::Console(console, _) => console.render(state),
+            Ui::Headless { .. } => {
+                println!("{}", outcome);
+                Ok(())
+            }
+        }
+    }
+
+    /// Drop the keys typed so far, which were meant for a run that is over.
+    pub fn clear_keys(&mut self) {
+        if let Ui::Console(_, Some(keys)) = self {
+            keys.clear();
+        }
+    }
+
+    /// Next key typed at the console, never if keys are not read.
+    pub async fn next_key(&mut self) -> Key {
+        match self {
+            Ui::Console(_, Some(keys)) => keys.next().await,
+            _ => futures::future::pending().await,
+        }
+    }
+
+    pub fn finalize(self, state: &State) -> Result<(), Error> {
+        match self {
+            Ui::Console(console, _) => console.finalize(state),
+            Ui::Headless { grid_rtt, .. } => {
+                for grid in grids(state)? {
+                    for line in grid_lines(&grid, grid_rtt)? {
+                        println!("{}", plain(&line));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The text of a line without its styling.
+fn plain(line: &Line) -> String {
+    let text: String = line.0.iter().map(|span| span.content()).collect();
+    text.trim_end().to_owned()
+}